@@ -0,0 +1,65 @@
+// Draws the greedy chunk meshes from a texture array, see `ChunkArrayMaterial`.
+
+#import bevy_pbr::mesh_functions as mesh_functions
+#import bevy_pbr::mesh_bindings mesh
+#import bevy_pbr::mesh_view_bindings view, fog
+#import bevy_pbr::mesh_view_types FOG_MODE_OFF
+#import bevy_pbr::pbr_functions as fns
+#import bevy_core_pipeline::tonemapping tone_mapping
+
+@group(1) @binding(0) var array_texture: texture_2d_array<f32>;
+@group(1) @binding(1) var array_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) layer: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) @interpolate(flat) layer: u32,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.world_position = mesh_functions::mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.position = mesh_functions::mesh_position_world_to_clip(out.world_position);
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(vertex.normal);
+    out.uv = vertex.uv;
+    out.color = vertex.color;
+    out.layer = vertex.layer;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    var pbr_input: fns::PbrInput = fns::pbr_input_new();
+
+    // The vertex colors are the ambient occlusion.
+    pbr_input.material.base_color = textureSample(array_texture, array_sampler, in.uv, i32(in.layer)) * in.color;
+    // The same as the material of the naive meshes.
+    pbr_input.material.metallic = 1.0;
+    pbr_input.material.reflectance = 1.0;
+
+    pbr_input.frag_coord = in.position;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = normalize(in.world_normal);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = pbr_input.world_normal;
+    pbr_input.V = fns::calculate_view(in.world_position, pbr_input.is_orthographic);
+
+    var color = fns::pbr(pbr_input);
+    if (fog.mode != FOG_MODE_OFF) {
+        color = fns::apply_fog(fog, color, in.world_position.xyz, view.world_position.xyz);
+    }
+
+    return tone_mapping(color, view.color_grading);
+}
@@ -0,0 +1,77 @@
+//! Measures the block generation and the meshing of a chunk separately.
+//!
+//! Generation is reported in blocks per second and meshing in vertices per second.
+
+use bevy::prelude::*;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use voxel_generation_rust::game::chunk::build_chunk_mesh_data;
+use voxel_generation_rust::game::common::*;
+use voxel_generation_rust::game::terrain::{generate_chunk_blocks, is_block, TerrainNoise};
+
+/// The widths of the cubic chunks. Chunks start at the bottom of the world, so these are caves and ores.
+const SIZES: [usize; 2] = [16, 32];
+
+fn config(size: usize) -> GenerationConfig {
+    GenerationConfig {
+        chunk_dims: ChunkDims::new(size, size, size),
+        ..default()
+    }
+}
+
+fn generation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_chunk_blocks");
+
+    for size in SIZES {
+        let config = config(size);
+        let noise = TerrainNoise::new(&config);
+        group.throughput(Throughput::Elements(config.chunk_volume() as u64));
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &config, |b, config| {
+            b.iter(|| generate_chunk_blocks(black_box(IVec2XZ::new(3, -2)), &noise, config))
+        });
+    }
+
+    group.finish();
+}
+
+fn meshing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_chunk_mesh_data");
+    let registry = BlockRegistry::default();
+
+    for size in SIZES {
+        let config = config(size);
+        let noise = TerrainNoise::new(&config);
+        let chunk_position = IVec2XZ::new(3, -2);
+        let chunk_blocks = generate_chunk_blocks(chunk_position, &noise, &config);
+        let neighbor_block = |pos| is_block(pos, &noise, &config);
+
+        let vertices = build_chunk_mesh_data(
+            &chunk_blocks,
+            chunk_position,
+            config.chunk_dims(),
+            neighbor_block,
+            &registry,
+        )
+        .vertices
+        .len();
+        group.throughput(Throughput::Elements(vertices as u64));
+
+        group.bench_with_input(BenchmarkId::from_parameter(size), &chunk_blocks, |b, blocks| {
+            b.iter(|| {
+                build_chunk_mesh_data(
+                    black_box(blocks),
+                    chunk_position,
+                    config.chunk_dims(),
+                    neighbor_block,
+                    &registry,
+                )
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, generation, meshing);
+criterion_main!(benches);
@@ -1,371 +1,1552 @@
+use bevy::ecs::query::Has;
 use bevy::tasks::AsyncComputeTaskPool;
 use bevy::{
+    pbr::NotShadowCaster,
     prelude::*,
     render::{
         mesh::{Indices, VertexAttributeValues},
+        primitives::Aabb,
         render_resource::PrimitiveTopology,
     },
 };
 use bevy_rapier3d::prelude::*;
 // use color_eyre::owo_colors::colors::xterm::BlueStone;
 use futures_lite::future;
-use noise::{NoiseFn, Perlin};
-use std::collections::HashSet;
-use std::time::Instant;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use super::common::*;
+use super::coords::{chunk_local_to_world, world_to_local};
+use super::light::{
+    apply_light, compute_skylight, propagate_block_light, LightGrid,
+};
+use super::terrain::*;
+use super::texture_array::{ChunkArrayMaterial, ChunkTextures, ATTRIBUTE_TEXTURE_LAYER};
+use super::world::VoxelWorld;
 
-/// Creates a 16x256x16 chunk mesh using a combination of 3D and 2D Perlin noise.
-fn create_chunk_mesh(chunk_position: IVec2XZ, game_texture: GameTextureAtlas) -> Mesh {
-    // Start the timer.
-    let start = Instant::now();
+/// Creates a chunk mesh using a combination of 3D and 2D Perlin noise, and its collider. The chunk is as big as the
+/// [`GenerationConfig::chunk_dims`].
+///
+/// This runs on the async compute pool, so everything expensive should happen here instead of in [`handle_mesh_tasks`].
+/// With [`GenerationConfig::synchronous`] it runs on the main thread instead.
+pub fn generate_chunk(
+    chunk_position: IVec2XZ,
+    lod: usize,
+    config: GenerationConfig,
+    registry: BlockRegistry,
+    modified: ModifiedChunks,
+    noise: Arc<TerrainNoise>,
+    pool: MeshBufferPool,
+) -> GeneratedChunk {
+
+    #[cfg(feature = "parallel")]
+    let mut chunk_blocks = generate_chunk_blocks_parallel(chunk_position, &noise, &config);
+    #[cfg(not(feature = "parallel"))]
+    let mut chunk_blocks = generate_chunk_blocks(chunk_position, &noise, &config);
+
+    // Put back the blocks the player changed.
+    modified.apply(chunk_position, &mut chunk_blocks);
+
+    let mut buffers = pool.take();
+    let mut generated_chunk = mesh_chunk(
+        chunk_position,
+        lod,
+        &chunk_blocks,
+        &ChunkNeighbors::new(),
+        &modified,
+        &noise,
+        &config,
+        &registry,
+        &mut buffers,
+    );
+    pool.give(buffers);
+    generated_chunk.blocks = Some(chunk_blocks);
 
-    // Create a new mesh.
-    let mut chunk_mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    generated_chunk
+}
 
-    let mut vertices: Vec<[f32; 3]> = Vec::new();
-    let mut indices: Vec<u32> = Vec::new();
-    let mut normals: Vec<[f32; 3]> = Vec::new();
-    let mut uvs: Vec<[f32; 2]> = Vec::new();
+/// Generates the blocks of a chunk with the `(block index, block)` overrides on top, and meshes them at full detail.
+///
+/// Goes through [`generate_chunk`] like the chunk tasks do, so tests and tools get the same blocks and mesh as the
+/// game, without needing any of its resources. Chunks are whole columns, so the position is only along x and z.
+pub fn regenerate_chunk(
+    chunk_position: IVec2XZ,
+    config: &GenerationConfig,
+    overrides: &[(usize, BlockType)],
+) -> (Vec<BlockType>, Mesh) {
+    let mut modified = ModifiedChunks {
+        dims: config.chunk_dims(),
+        ..default()
+    };
+    for &(index, block) in overrides {
+        modified.record(chunk_position, index, block);
+    }
 
-    // Generate an array of Blocks, representing whether a cube should be created at that position.
-    let mut chunk_blocks: [[[BlockType; CHUNK_SIZE]; CHUNK_HEIGHT]; CHUNK_SIZE] =
-        [[[BlockType::Air; CHUNK_SIZE]; CHUNK_HEIGHT]; CHUNK_SIZE];
+    let generated_chunk = generate_chunk(
+        chunk_position,
+        1,
+        *config,
+        BlockRegistry::default(),
+        modified,
+        Arc::new(TerrainNoise::new(config)),
+        MeshBufferPool::default(),
+    );
+    let blocks = generated_chunk
+        .blocks
+        .expect("generated chunks always have their blocks");
 
-    // Create a 3D Perlin noise function with a random seed for the cave and surface generation
-    let perlin = Perlin::new(SEED);
+    (blocks, generated_chunk.mesh)
+}
 
-    // Loop over each block position in the chunk.
-    // Remember to offset the position by the chunk position.
-    #[allow(clippy::needless_range_loop)]
-    for x in 0..CHUNK_SIZE {
-        for y in 0..CHUNK_HEIGHT {
-            for z in 0..CHUNK_SIZE {
-                // Scale the position down by the chunk size.
-                let scaled_x = x as i32 + (chunk_position.x * CHUNK_SIZE as i32);
-                let scaled_y = y as i32;
-                let scaled_z = z as i32 + (chunk_position.z * CHUNK_SIZE as i32);
-
-                // Sample the noise function at the scaled position.
-                // The perlin noise needs a float value, so we need to cast the scaled position to a float.
-                chunk_blocks[x][y][z] = is_block(IVec3::new(scaled_x, scaled_y, scaled_z), &perlin);
+/// The blocks of the loaded chunks next to a chunk that is being meshed.
+pub type ChunkNeighbors = HashMap<IVec2XZ, Vec<BlockType>>;
+
+/// Meshes the blocks of a chunk and creates its collider.
+///
+/// Blocks in the `neighbors` are used for culling the chunk borders, any other blocks outside of the chunk are sampled
+/// straight from the noise, with the `modified` blocks on top. The naive meshes are built in the `buffers`.
+#[allow(clippy::too_many_arguments)]
+fn mesh_chunk(
+    chunk_position: IVec2XZ,
+    lod: usize,
+    chunk_blocks: &[BlockType],
+    neighbors: &ChunkNeighbors,
+    modified: &ModifiedChunks,
+    noise: &TerrainNoise,
+    config: &GenerationConfig,
+    registry: &BlockRegistry,
+    buffers: &mut MeshBuffers,
+) -> GeneratedChunk {
+    let dims = config.chunk_dims();
+    let neighbor_block = |neighbor_block_pos| {
+        neighbors
+            .get(&IVec2XZ::from_block(neighbor_block_pos, dims))
+            .zip(world_block_index(neighbor_block_pos, dims))
+            .and_then(|(neighbor_blocks, index)| neighbor_blocks.get(index).copied())
+            .or_else(|| modified.block(neighbor_block_pos, dims))
+            .unwrap_or_else(|| is_block(neighbor_block_pos, noise, config))
+    };
+    // Only the plain naive meshes can be patched after an edit, see `patch_chunk_mesh`.
+    let mut faces = None;
+    let (mut mesh, mut water_mesh) = if lod > 1 {
+        build_lod_mesh(
+            chunk_blocks,
+            chunk_position,
+            dims,
+            lod,
+            config.meshing,
+            registry,
+            config.min_face_voxels as usize,
+        )
+    } else {
+        let mesh = match config.meshing {
+            MeshingMode::Naive | MeshingMode::PerBlock | MeshingMode::Lod(_) => {
+                build_chunk_mesh_into(
+                    buffers,
+                    chunk_blocks,
+                    chunk_position,
+                    dims,
+                    &neighbor_block,
+                    registry,
+                );
+                if config.dedup_vertices {
+                    let deduped = buffers.to_data().dedup_vertices();
+                    info!(
+                        "Chunk {:?} vertices deduplicated from {} to {}",
+                        chunk_position,
+                        buffers.vertices.len(),
+                        deduped.vertices.len()
+                    );
+                    deduped.into_mesh()
+                } else {
+                    let lit = config.sky_light || config.block_light;
+                    let normals_changed = config.computed_normals || config.smooth_normals;
+                    if config.meshing != MeshingMode::PerBlock && !lit && !normals_changed {
+                        faces = Some(buffers.faces.clone());
+                    }
+                    buffers.to_mesh()
+                }
+            }
+            MeshingMode::Greedy => greedy_mesh(
+                chunk_blocks,
+                chunk_position,
+                dims,
+                &neighbor_block,
+                registry,
+            ),
+        };
+        build_water_mesh_into(
+            buffers,
+            chunk_blocks,
+            chunk_position,
+            dims,
+            &neighbor_block,
+            registry,
+        );
+        (mesh, buffers.to_mesh())
+    };
+    let water_faces = water_mesh.indices().map_or(0, |indices| indices.len() / 6);
+
+    // The combined mesh is still needed for the collider and the stats, the block meshes are only drawn.
+    let per_block = lod == 1 && config.meshing == MeshingMode::PerBlock;
+    let mut block_meshes = if per_block {
+        build_block_meshes(
+            chunk_blocks,
+            chunk_position,
+            dims,
+            &neighbor_block,
+            registry,
+            buffers,
+        )
+    } else {
+        Vec::new()
+    };
+
+    debug_validate_mesh(&mesh, chunk_position);
+    debug_validate_mesh(&water_mesh, chunk_position);
+    for (_, block_mesh) in &block_meshes {
+        debug_validate_mesh(block_mesh, chunk_position);
+    }
+
+    // Needs the vertices in world space. The coarse meshes are too far away to see the light anyway.
+    let light_grids = [
+        (config.sky_light && lod == 1).then(|| compute_skylight(chunk_blocks, dims, registry)),
+        (config.block_light && lod == 1)
+            .then(|| propagate_block_light(chunk_blocks, dims, registry)),
+    ];
+    if let Some(light) = light_grids.into_iter().flatten().reduce(LightGrid::max) {
+        apply_light(&mut mesh, &light, chunk_position);
+        apply_light(&mut water_mesh, &light, chunk_position);
+        for (_, block_mesh) in &mut block_meshes {
+            apply_light(block_mesh, &light, chunk_position);
+        }
+    }
+
+    // Far from the origin the vertices lose precision in world space, so the chunk entity is moved there instead.
+    let translation = if config.local_space {
+        let origin = chunk_position.origin(dims).as_vec3();
+        translate_mesh(&mut mesh, -origin);
+        translate_mesh(&mut water_mesh, -origin);
+        for (_, block_mesh) in &mut block_meshes {
+            translate_mesh(block_mesh, -origin);
+        }
+        origin
+    } else {
+        Vec3::ZERO
+    };
+
+    // A point light in the middle of every glowing block.
+    let lights = chunk_blocks
+        .iter()
+        .enumerate()
+        .map(|(index, &block)| (index, registry.get(block).emissive))
+        .filter(|(_, emissive)| *emissive != Color::BLACK)
+        .map(|(index, emissive)| {
+            let (x, y, z) = block_position(index, dims);
+            let local = IVec3::new(x as i32, y as i32, z as i32);
+            let center = chunk_local_to_world(chunk_position, local, dims).as_vec3() + 0.5;
+            (center - translation, emissive)
+        })
+        .collect();
+
+    // Get the vertices and indices from the mesh. This is needed to create the collider.
+    let (vertices, indices) = get_verts_indices(&mesh);
+    let stats = ChunkStats {
+        air: chunk_blocks.iter().filter(|&&block| block == BlockType::Air).count() as u32,
+        solid: chunk_blocks.iter().filter(|&&block| registry.get(block).solid).count() as u32,
+        faces: (indices.len() / 2 + water_faces) as u32,
+        vertices: (vertices.len() + water_mesh.count_vertices()) as u32,
+    };
+    // The water isn't solid, so it's left out of the collider.
+    let collider = (!vertices.is_empty()).then(|| Collider::trimesh(vertices, indices));
+
+    // After the collider, which needs the indices.
+    if config.computed_normals {
+        compute_normals(&mut mesh);
+        compute_normals(&mut water_mesh);
+        for (_, block_mesh) in &mut block_meshes {
+            compute_normals(block_mesh);
+        }
+    }
+    if config.smooth_normals {
+        smooth_normals(&mut mesh);
+        smooth_normals(&mut water_mesh);
+        for (_, block_mesh) in &mut block_meshes {
+            smooth_normals(block_mesh);
+        }
+    }
+
+    GeneratedChunk {
+        mesh,
+        // The water has its own block mesh already.
+        water_mesh: (water_faces > 0 && !per_block).then_some(water_mesh),
+        block_meshes,
+        collider,
+        blocks: None,
+        stats,
+        translation,
+        lights,
+        faces,
+        // Measured around the whole task in `mesh_task`.
+        gen_time: Duration::ZERO,
+    }
+}
+
+/// Panics with the chunk position if [`validate_mesh`] finds the mesh broken. Only checks in debug builds, like
+/// `debug_assert!`.
+fn debug_validate_mesh(mesh: &Mesh, chunk_position: IVec2XZ) {
+    if cfg!(debug_assertions) {
+        if let Err(error) = validate_mesh(mesh) {
+            panic!("Broken mesh of chunk {:?}: {}", chunk_position, error);
+        }
+    }
+}
+
+/// Checks that every vertex attribute of the mesh has a value for each position, and that the indices only point at
+/// existing vertices.
+pub fn validate_mesh(mesh: &Mesh) -> Result<(), String> {
+    let vertex_count = mesh
+        .attribute(Mesh::ATTRIBUTE_POSITION)
+        .map_or(0, VertexAttributeValues::len);
+
+    for (name, attribute) in [
+        ("normals", Mesh::ATTRIBUTE_NORMAL),
+        ("UVs", Mesh::ATTRIBUTE_UV_0),
+        ("colors", Mesh::ATTRIBUTE_COLOR),
+        ("texture layers", ATTRIBUTE_TEXTURE_LAYER),
+    ] {
+        if let Some(values) = mesh.attribute(attribute) {
+            if values.len() != vertex_count {
+                return Err(format!(
+                    "{} {} for {} positions",
+                    values.len(),
+                    name,
+                    vertex_count
+                ));
             }
         }
     }
 
-    // From now on, we don't need the chunk position anymore, so we can just use the local block position.
+    if let Some(indices) = mesh.indices() {
+        if let Some((position, index)) = indices
+            .iter()
+            .enumerate()
+            .find(|&(_, index)| index >= vertex_count)
+        {
+            return Err(format!(
+                "index {} at {} is out of range of {} vertices",
+                index, position, vertex_count
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the mesh of already generated chunk blocks, see [`build_chunk_mesh_data`].
+pub fn build_chunk_mesh(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+) -> Mesh {
+    build_chunk_mesh_data(
+        chunk_blocks,
+        chunk_position,
+        dims,
+        neighbor_block,
+        registry,
+    )
+    .into_mesh()
+}
+
+/// Builds a coarse mesh of the chunk and its water, where every cube is `lod` blocks wide. Used for the distant
+/// chunks.
+///
+/// The faces on the chunk border are never culled, the chunk next to it might be finer and have gaps where this one
+/// is solid. Those faces are hidden by the neighbor, so there are no holes between the levels of detail.
+///
+/// Greedy quads smaller than `min_face_voxels` full size blocks are dropped, see [`greedy_mesh_simplified`].
+pub fn build_lod_mesh(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    lod: usize,
+    meshing: MeshingMode,
+    registry: &BlockRegistry,
+    min_face_voxels: usize,
+) -> (Mesh, Mesh) {
+    let (lod_blocks, lod_dims) = downsample_blocks(chunk_blocks, dims, lod);
+
+    // Mesh it as the chunk at the origin, then scale it up and move it into place.
+    let air = |_: IVec3| BlockType::Air;
+    let mut mesh = match meshing {
+        MeshingMode::Naive | MeshingMode::PerBlock | MeshingMode::Lod(_) => {
+            build_chunk_mesh(&lod_blocks, IVec2XZ::new(0, 0), lod_dims, air, registry)
+        }
+        MeshingMode::Greedy => {
+            // Every block of the coarse mesh covers lod by lod blocks of the face. Rounded up, so the farther chunks
+            // are never simplified less than the closer ones.
+            let min_face_blocks = min_face_voxels.div_ceil(lod * lod);
+            let position = IVec2XZ::new(0, 0);
+            greedy_mesh_simplified(&lod_blocks, position, lod_dims, air, registry, min_face_blocks)
+        }
+    };
+    let mut water_mesh =
+        build_water_mesh(&lod_blocks, IVec2XZ::new(0, 0), lod_dims, air, registry);
+
+    let origin = chunk_position.origin(dims).as_vec3();
+    for mesh in [&mut mesh, &mut water_mesh] {
+        if let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+        {
+            for position in positions {
+                *position = (Vec3::from_array(*position) * lod as f32 + origin).to_array();
+            }
+        }
+    }
+
+    (mesh, water_mesh)
+}
+
+/// Gives every vertex the average normal of all the vertices at its position, see
+/// [`GenerationConfig::smooth_normals`]. The vertices of the faces meeting at a corner aren't merged, they only
+/// share the normal, so the textures stay as they are.
+pub fn smooth_normals(mesh: &mut Mesh) {
+    let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return;
+    };
+    let corners: Vec<[u32; 3]> = positions
+        .iter()
+        .map(|&position| position.map(f32::to_bits))
+        .collect();
+
+    let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        return;
+    };
+    let mut sums: HashMap<[u32; 3], Vec3> = HashMap::new();
+    for (corner, normal) in corners.iter().zip(normals.iter()) {
+        *sums.entry(*corner).or_default() += Vec3::from_array(*normal);
+    }
+    for (corner, normal) in corners.iter().zip(normals.iter_mut()) {
+        *normal = sums[corner].normalize_or_zero().to_array();
+    }
+}
+
+/// Replaces the normals of the mesh with ones computed from its triangles, see [`GenerationConfig::computed_normals`].
+///
+/// Every triangle gets its own vertices, so the mesh isn't indexed afterwards.
+pub fn compute_normals(mesh: &mut Mesh) {
+    mesh.duplicate_vertices();
+    mesh.compute_flat_normals();
+}
+
+/// Moves every vertex of the mesh by the offset.
+pub fn translate_mesh(mesh: &mut Mesh, offset: Vec3) {
+    if let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+    {
+        for position in positions {
+            *position = (Vec3::from_array(*position) + offset).to_array();
+        }
+    }
+}
+
+/// Shrinks the chunk blocks by `stride` along every axis. Each group of blocks becomes its topmost solid block, so the
+/// group is solid if any block in it is, and the surface keeps its grass. Only groups without any solid block become
+/// their topmost water or lava, so the shores don't sink below the water.
+///
+/// Groups on the far side of the chunk are cut short if the chunk size isn't a multiple of the stride.
+pub fn downsample_blocks(
+    chunk_blocks: &[BlockType],
+    dims: ChunkDims,
+    stride: usize,
+) -> (Vec<BlockType>, ChunkDims) {
+    let group =
+        |lod_coord: usize, size: usize| lod_coord * stride..((lod_coord + 1) * stride).min(size);
+    let lod_dims = ChunkDims::new(
+        (dims.x + stride - 1) / stride,
+        (dims.y + stride - 1) / stride,
+        (dims.z + stride - 1) / stride,
+    );
+
+    let lod_blocks = (0..lod_dims.volume())
+        .map(|lod_index| {
+            let (lod_x, lod_y, lod_z) = block_position(lod_index, lod_dims);
+
+            // From the top down.
+            let blocks = move || {
+                group(lod_y, dims.y)
+                    .rev()
+                    .flat_map(move |y| {
+                        group(lod_x, dims.x)
+                            .flat_map(move |x| group(lod_z, dims.z).map(move |z| (x, y, z)))
+                    })
+                    .map(move |(x, y, z)| chunk_blocks[block_index(x, y, z, dims)])
+            };
+
+            blocks()
+                .find(|block| block.is_solid())
+                .or_else(|| blocks().find(|&block| block != BlockType::Air))
+                .unwrap_or(BlockType::Air)
+        })
+        .collect();
+
+    (lod_blocks, lod_dims)
+}
+
+/// The vertex data of a chunk mesh, before it's turned into a [`Mesh`].
+///
+/// The vertices are in world space, the chunk position is already baked into them. That's why every chunk entity
+/// stays at the origin, see [`handle_mesh_tasks`]. With [`GenerationConfig::local_space`] they are moved relative to
+/// the chunk after meshing, and the entity is moved to the chunk instead.
+#[derive(Default)]
+pub struct ChunkMeshData {
+    pub vertices: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub colors: Vec<[f32; 4]>,
+}
+
+impl ChunkMeshData {
+    /// Converts the vectors to VertexAttributeValues and adds them to a new mesh.
+    pub fn into_mesh(self) -> Mesh {
+        let mut chunk_mesh = Mesh::new(PrimitiveTopology::TriangleList);
+
+        chunk_mesh.insert_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            VertexAttributeValues::Float32x3(self.vertices),
+        );
+        chunk_mesh.insert_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            VertexAttributeValues::Float32x3(self.normals),
+        );
+        chunk_mesh.insert_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            VertexAttributeValues::Float32x2(self.uvs),
+        );
+        // The standard material multiplies its color with the vertex colors, which darkens the occluded corners.
+        chunk_mesh.insert_attribute(
+            Mesh::ATTRIBUTE_COLOR,
+            VertexAttributeValues::Float32x4(self.colors),
+        );
+        let vertex_count = self.vertices.len();
+        chunk_mesh.set_indices(Some(compact_indices(self.indices, vertex_count)));
+
+        chunk_mesh
+    }
+
+    /// Merges the vertices that have the same position, normal, UV and color, and points the indices at the one that
+    /// is left. The ambient occlusion is in the colors, so corners that are shaded differently stay apart.
+    pub fn dedup_vertices(self) -> Self {
+        let mut deduped = Self::default();
+        let mut merged: HashMap<[u32; 12], u32> = HashMap::new();
+
+        let mut remap = Vec::with_capacity(self.vertices.len());
+        for i in 0..self.vertices.len() {
+            let mut key = [0; 12];
+            let attributes = self.vertices[i]
+                .iter()
+                .chain(&self.normals[i])
+                .chain(&self.uvs[i])
+                .chain(&self.colors[i]);
+            for (slot, value) in key.iter_mut().zip(attributes) {
+                *slot = value.to_bits();
+            }
+
+            let index = *merged.entry(key).or_insert_with(|| {
+                deduped.vertices.push(self.vertices[i]);
+                deduped.normals.push(self.normals[i]);
+                deduped.uvs.push(self.uvs[i]);
+                deduped.colors.push(self.colors[i]);
+                deduped.vertices.len() as u32 - 1
+            });
+            remap.push(index);
+        }
+        deduped.indices = self.indices.iter().map(|&index| remap[index as usize]).collect();
+
+        deduped
+    }
+
+    /// Copies the vertex data back out of a mesh built by [`ChunkMeshData::into_mesh`]. `None` if an attribute is
+    /// missing.
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        let (
+            Some(VertexAttributeValues::Float32x3(vertices)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Float32x2(uvs)),
+            Some(VertexAttributeValues::Float32x4(colors)),
+            Some(indices),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+            mesh.attribute(Mesh::ATTRIBUTE_COLOR),
+            mesh.indices(),
+        )
+        else {
+            return None;
+        };
+
+        Some(Self {
+            vertices: vertices.clone(),
+            indices: indices.iter().map(|index| index as u32).collect(),
+            normals: normals.clone(),
+            uvs: uvs.clone(),
+            colors: colors.clone(),
+        })
+    }
+}
+
+/// Stores the indices as `u16` if every vertex can be reached with one, which halves the index buffer. Most chunks
+/// have less vertices than that, only very broken up ones need `u32` indices.
+pub fn compact_indices(indices: Vec<u32>, vertex_count: usize) -> Indices {
+    if vertex_count <= u16::MAX as usize {
+        Indices::U16(indices.into_iter().map(|index| index as u16).collect())
+    } else {
+        Indices::U32(indices)
+    }
+}
+
+/// Scratch vectors the vertex data of a chunk is built in. They are cleared for every chunk but keep their capacity,
+/// so re-meshing many chunks doesn't allocate them over and over. See [`MeshBufferPool`].
+#[derive(Default)]
+pub struct MeshBuffers {
+    pub vertices: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub colors: Vec<[f32; 4]>,
+    /// The block and face of every quad, in the order they are in the vertex data. Only filled by the naive meshing,
+    /// see [`ChunkFaces`].
+    pub faces: Vec<(IVec3, BlockFace)>,
+}
+
+impl MeshBuffers {
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.normals.clear();
+        self.uvs.clear();
+        self.colors.clear();
+        self.faces.clear();
+    }
+
+    /// Copies the vertex data into a new mesh, the buffers can be reused afterwards.
+    pub fn to_mesh(&self) -> Mesh {
+        self.to_data().into_mesh()
+    }
+
+    /// Copies the vertex data, the buffers can be reused afterwards.
+    pub fn to_data(&self) -> ChunkMeshData {
+        ChunkMeshData {
+            vertices: self.vertices.clone(),
+            indices: self.indices.clone(),
+            normals: self.normals.clone(),
+            uvs: self.uvs.clone(),
+            colors: self.colors.clone(),
+        }
+    }
+
+    /// Moves the vertex data out without copying it, for when the buffers aren't reused.
+    pub fn into_data(self) -> ChunkMeshData {
+        ChunkMeshData {
+            vertices: self.vertices,
+            indices: self.indices,
+            normals: self.normals,
+            uvs: self.uvs,
+            colors: self.colors,
+        }
+    }
+}
+
+/// The [`MeshBuffers`] shared by the meshing tasks. A task takes a set of buffers while it's meshing and gives them
+/// back when it's done, so there are only ever as many sets as tasks running at once.
+#[derive(Resource, Clone, Default)]
+pub struct MeshBufferPool(Arc<Mutex<Vec<MeshBuffers>>>);
+
+impl MeshBufferPool {
+    /// Takes a set of buffers from the pool, or new ones if they are all in use.
+    pub fn take(&self) -> MeshBuffers {
+        self.0.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    /// Puts the buffers back, so the next task can reuse them.
+    pub fn give(&self, buffers: MeshBuffers) {
+        self.0.lock().unwrap().push(buffers);
+    }
+}
+
+/// Builds the vertex data of already generated chunk blocks. This doesn't need Bevy running, so it can be tested.
+///
+/// A face is only created when the block next to it lets you see through (see [`face_visible`]),
+/// so a chunk full of blocks only produces its outer shell. Blocks outside of the chunk are
+/// looked up with `neighbor_block`, which gets a world position.
+///
+/// Translucent blocks like water are left out, they go into their own mesh, see [`build_water_mesh_data`].
+pub fn build_chunk_mesh_data(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+) -> ChunkMeshData {
+    let mut buffers = MeshBuffers::default();
+    build_chunk_mesh_into(
+        &mut buffers,
+        chunk_blocks,
+        chunk_position,
+        dims,
+        neighbor_block,
+        registry,
+    );
+    buffers.into_data()
+}
+
+/// Same as [`build_chunk_mesh_data`], but builds the vertex data in the cleared `buffers`.
+pub fn build_chunk_mesh_into(
+    buffers: &mut MeshBuffers,
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+) {
+    build_mesh_data(
+        buffers,
+        chunk_blocks,
+        chunk_position,
+        dims,
+        neighbor_block,
+        registry,
+        |block| !registry.get(block).translucent,
+    );
+}
+
+/// Builds the mesh of the translucent blocks of a chunk, see [`build_water_mesh_data`].
+pub fn build_water_mesh(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+) -> Mesh {
+    build_water_mesh_data(
+        chunk_blocks,
+        chunk_position,
+        dims,
+        neighbor_block,
+        registry,
+    )
+    .into_mesh()
+}
+
+/// Builds the vertex data of only the translucent blocks of a chunk, which are drawn with a see-through material.
+pub fn build_water_mesh_data(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+) -> ChunkMeshData {
+    let mut buffers = MeshBuffers::default();
+    build_water_mesh_into(
+        &mut buffers,
+        chunk_blocks,
+        chunk_position,
+        dims,
+        neighbor_block,
+        registry,
+    );
+    buffers.into_data()
+}
+
+/// Same as [`build_water_mesh_data`], but builds the vertex data in the cleared `buffers`.
+pub fn build_water_mesh_into(
+    buffers: &mut MeshBuffers,
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+) {
+    build_mesh_data(
+        buffers,
+        chunk_blocks,
+        chunk_position,
+        dims,
+        neighbor_block,
+        registry,
+        |block| registry.get(block).translucent,
+    );
+}
+
+/// Builds one mesh for each block type in the chunk, so every block type can have its own material. Faces are culled
+/// the same as in [`build_chunk_mesh_data`], translucent blocks included.
+pub fn build_block_meshes(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+    buffers: &mut MeshBuffers,
+) -> Vec<(BlockType, Mesh)> {
+    BlockType::ALL
+        .into_iter()
+        .filter(|&block| block != BlockType::Air && chunk_blocks.contains(&block))
+        .filter_map(|block| {
+            build_mesh_data(
+                buffers,
+                chunk_blocks,
+                chunk_position,
+                dims,
+                &neighbor_block,
+                registry,
+                |other| other == block,
+            );
+            (!buffers.vertices.is_empty()).then(|| (block, buffers.to_mesh()))
+        })
+        .collect()
+}
+
+/// Builds the vertex data of the blocks of a chunk that are `included` in the `buffers`.
+fn build_mesh_data(
+    buffers: &mut MeshBuffers,
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+    included: impl Fn(BlockType) -> bool,
+) {
+    buffers.clear();
+
     // Now that the chunk data is generated, check the neighbouring blocks to see if we need to create faces.
     // Loop over each block position in the chunk.
-    for x in 0..CHUNK_SIZE {
-        for y in 0..CHUNK_HEIGHT {
-            for z in 0..CHUNK_SIZE {
+    for x in 0..dims.x {
+        for y in 0..dims.y {
+            for z in 0..dims.z {
                 // Get the block type at the current position.
-                let block_type = chunk_blocks[x][y][z];
+                let block_type = chunk_blocks[block_index(x, y, z, dims)];
 
-                // If the block is Air, we don't need to create any faces.
-                if block_type == BlockType::Air {
+                // If the block is Air or belongs in another mesh, we don't need to create any faces.
+                if block_type == BlockType::Air || !included(block_type) {
                     continue;
                 }
 
-                if block_type == BlockType::Water {}
-
-                // Check the blocks around the current block to see if we need to create faces.
-                for &(x_offset, y_offset, z_offset, face) in &[
-                    (0, 1, 0, BlockFace::Top),
-                    (0, -1, 0, BlockFace::Bottom),
-                    (1, 0, 0, BlockFace::Right),
-                    (-1, 0, 0, BlockFace::Left),
-                    (0, 0, 1, BlockFace::Front),
-                    (0, 0, -1, BlockFace::Back),
-                ] {
-                    let neighbor_x = x as i32 + x_offset;
-                    let neighbor_y = y as i32 + y_offset;
-                    let neighbor_z = z as i32 + z_offset;
-
-                    // Check if the neighbor block is outside the chunk.
-                    if neighbor_x < 0
-                        || neighbor_x >= CHUNK_SIZE as i32
-                        || neighbor_y < 0
-                        || neighbor_y >= CHUNK_HEIGHT as i32
-                        || neighbor_z < 0
-                        || neighbor_z >= CHUNK_SIZE as i32
-                    {
-                        // If the neighbor block is outside the chunk, we need to calculate if there is block in other chunk.
-                        let neighbor_block_pos = IVec3::new(
-                            x as i32 + (chunk_position.x * CHUNK_SIZE as i32) + x_offset,
-                            y as i32 + y_offset,
-                            z as i32 + (chunk_position.z * CHUNK_SIZE as i32) + z_offset,
+                push_block_faces(
+                    buffers,
+                    chunk_blocks,
+                    chunk_position,
+                    dims,
+                    IVec3::new(x as i32, y as i32, z as i32),
+                    &neighbor_block,
+                    registry,
+                );
+            }
+        }
+    }
+}
+
+/// Pushes the visible faces of the block at `local_pos` with their ambient occlusion, and records them in
+/// [`MeshBuffers::faces`].
+fn push_block_faces(
+    buffers: &mut MeshBuffers,
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    local_pos: IVec3,
+    neighbor_block: &impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+) {
+    let MeshBuffers {
+        vertices,
+        indices,
+        normals,
+        uvs,
+        colors,
+        faces,
+    } = buffers;
+    let block_type = chunk_blocks[block_index(
+        local_pos.x as usize,
+        local_pos.y as usize,
+        local_pos.z as usize,
+        dims,
+    )];
+
+    // Check the blocks around the current block to see if we need to create faces.
+    let mut exposed = FaceMask::default();
+    for face in BlockFace::ALL {
+        if face_exposed(
+            chunk_blocks,
+            chunk_position,
+            dims,
+            local_pos,
+            face,
+            neighbor_block,
+            registry,
+        ) {
+            exposed.set_face(face);
+        }
+    }
+
+    // Create the faces.
+    for face in exposed.exposed_faces() {
+        push_face(
+            vertices,
+            indices,
+            normals,
+            uvs,
+            block_world_position(chunk_position, dims, local_pos),
+            face,
+            block_type,
+            registry,
+        );
+
+        let ao = chunk_face_ao(
+            chunk_blocks,
+            chunk_position,
+            dims,
+            local_pos,
+            face,
+            block_type,
+            neighbor_block,
+            registry,
+        );
+        push_ao(colors, indices, ao);
+        faces.push((local_pos, face));
+    }
+}
+
+/// Rebuilds only the faces around the `edited` blocks of a naive chunk mesh instead of the whole chunk, which keeps
+/// single block edits fast in big chunks. Every block touching an edit, diagonals included, has its faces removed and
+/// pushed again, so the ambient occlusion around the edit is right too. The `edited` positions are relative to the
+/// chunk, and the new vertices are moved by `-translation` like the rest of the mesh, see
+/// [`GenerationConfig::local_space`].
+///
+/// `faces` are the [`ChunkFaces`] of the mesh and are kept up to date. Returns `false` without changing anything if
+/// they don't match the mesh, then it has to be rebuilt. The translucent blocks aren't in this mesh, so their faces
+/// aren't patched.
+#[allow(clippy::too_many_arguments)]
+pub fn patch_chunk_mesh(
+    data: &mut ChunkMeshData,
+    faces: &mut Vec<(IVec3, BlockFace)>,
+    edited: &[IVec3],
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+    translation: Vec3,
+) -> bool {
+    let quads = faces.len();
+    if data.vertices.len() != quads * 4
+        || data.normals.len() != quads * 4
+        || data.uvs.len() != quads * 4
+        || data.colors.len() != quads * 4
+        || data.indices.len() != quads * 6
+    {
+        return false;
+    }
+
+    let touched = |pos: IVec3| edited.iter().any(|&edit| (pos - edit).abs().max_element() <= 1);
+
+    // The last quad is moved into the hole of a removed one, so nothing else has to shift.
+    let mut quad = 0;
+    while quad < faces.len() {
+        if !touched(faces[quad].0) {
+            quad += 1;
+            continue;
+        }
+
+        let last = faces.len() - 1;
+        faces.swap_remove(quad);
+        swap_remove_quad(&mut data.vertices, quad, 4);
+        swap_remove_quad(&mut data.normals, quad, 4);
+        swap_remove_quad(&mut data.uvs, quad, 4);
+        swap_remove_quad(&mut data.colors, quad, 4);
+        // The indices of the moved quad point at its old vertices.
+        swap_remove_quad(&mut data.indices, quad, 6);
+        for index in &mut data.indices[quad * 6..(quad * 6 + 6).min(last * 6)] {
+            *index = *index - last as u32 * 4 + quad as u32 * 4;
+        }
+    }
+
+    let mut buffers = MeshBuffers {
+        vertices: std::mem::take(&mut data.vertices),
+        indices: std::mem::take(&mut data.indices),
+        normals: std::mem::take(&mut data.normals),
+        uvs: std::mem::take(&mut data.uvs),
+        colors: std::mem::take(&mut data.colors),
+        faces: std::mem::take(faces),
+    };
+    let first_new_vertex = buffers.vertices.len();
+
+    // Every block touching an edit gets its faces back, each block once.
+    let around = (-1..=1)
+        .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))));
+    let mut rebuilt = HashSet::new();
+    for pos in edited
+        .iter()
+        .flat_map(|&edit| around.clone().map(move |offset| edit + offset))
+    {
+        let Some(block) = try_block(chunk_blocks, pos, dims) else {
+            continue;
+        };
+        if !rebuilt.insert(pos) || block == BlockType::Air || registry.get(block).translucent {
+            continue;
+        }
+        push_block_faces(
+            &mut buffers,
+            chunk_blocks,
+            chunk_position,
+            dims,
+            pos,
+            &neighbor_block,
+            registry,
+        );
+    }
+
+    for vertex in &mut buffers.vertices[first_new_vertex..] {
+        *vertex = (Vec3::from_array(*vertex) - translation).to_array();
+    }
+
+    *faces = std::mem::take(&mut buffers.faces);
+    *data = buffers.into_data();
+    true
+}
+
+/// Removes the `size` values of a quad by moving the ones of the last quad over them.
+fn swap_remove_quad<T: Copy>(values: &mut Vec<T>, quad: usize, size: usize) {
+    let last = values.len() - size;
+    values.copy_within(last.., quad * size);
+    values.truncate(last);
+}
+
+/// Builds the mesh of already generated chunk blocks like [`build_chunk_mesh`], but merges neighbouring faces.
+///
+/// For every face direction the chunk is cut into slices, and the visible faces of the same block in a slice are
+/// grown into rectangles, first along one axis and then along the other. The UVs of a merged quad go from 0 to its
+/// size in blocks, so the texture tiles once per block instead of stretching. Which texture is in the
+/// [`ATTRIBUTE_TEXTURE_LAYER`], the mesh is drawn with a [`ChunkArrayMaterial`]. Translucent blocks are left out like
+/// in [`build_chunk_mesh_data`].
+pub fn greedy_mesh(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+) -> Mesh {
+    greedy_mesh_simplified(chunk_blocks, chunk_position, dims, neighbor_block, registry, 0)
+}
+
+/// Same as [`greedy_mesh`], but the merged quads covering less than `min_face_blocks` blocks are dropped. That leaves
+/// holes, so it's only meant for chunks far enough away that the small faces are barely a pixel.
+pub fn greedy_mesh_simplified(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    neighbor_block: impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+    min_face_blocks: usize,
+) -> Mesh {
+    let mut vertices: Vec<[f32; 3]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+    let mut layers: Vec<u32> = Vec::new();
+
+    let size = [dims.x, dims.y, dims.z];
+
+    for face in BlockFace::ALL {
+        // The axis the face points along, and the two axes of the slices.
+        let normal_axis = face.axis();
+        let (u_axis, v_axis) = ((normal_axis + 1) % 3, (normal_axis + 2) % 3);
+        let (u_size, v_size) = (size[u_axis], size[v_axis]);
+
+        let local_pos = |depth: usize, u: usize, v: usize| {
+            let mut pos = IVec3::ZERO;
+            pos[normal_axis] = depth as i32;
+            pos[u_axis] = u as i32;
+            pos[v_axis] = v as i32;
+            pos
+        };
+
+        for depth in 0..size[normal_axis] {
+            // The block and the ambient occlusion of every visible face in this slice. Faces are only merged when
+            // both match, so the corners of a merged quad are shaded like the faces it replaces.
+            let mut mask: Vec<Option<(BlockType, [u8; 4])>> = Vec::with_capacity(u_size * v_size);
+            for v in 0..v_size {
+                for u in 0..u_size {
+                    let pos = local_pos(depth, u, v);
+                    let block = chunk_blocks[block_index(
+                        pos.x as usize,
+                        pos.y as usize,
+                        pos.z as usize,
+                        dims,
+                    )];
+                    let exposed = block != BlockType::Air
+                        && !registry.get(block).translucent
+                        && face_exposed(
+                            chunk_blocks,
+                            chunk_position,
+                            dims,
+                            pos,
+                            face,
+                            &neighbor_block,
+                            registry,
+                        );
+                    mask.push(exposed.then(|| {
+                        let ao = chunk_face_ao(
+                            chunk_blocks,
+                            chunk_position,
+                            dims,
+                            pos,
+                            face,
+                            block,
+                            &neighbor_block,
+                            registry,
                         );
-                        let neighbor_block_type = is_block(neighbor_block_pos, &perlin);
-                        if neighbor_block_type == BlockType::Air
-                            || (block_type != BlockType::Water
-                                && neighbor_block_type == BlockType::Water)
-                            || (block_type != BlockType::Lava
-                                && neighbor_block_type == BlockType::Lava)
-                            || neighbor_block_pos.y < 0
-                            || neighbor_block_pos.y > CHUNK_HEIGHT as i32
-                        {
-                            // Create the face.
-                            create_face(
-                                &mut vertices,
-                                &mut indices,
-                                &mut normals,
-                                &mut uvs,
-                                IVec2XZ::new(chunk_position.x, chunk_position.z),
-                                [x as f32, y as f32, z as f32],
-                                face,
-                                block_type,
-                                &game_texture.0.textures,
-                                &game_texture.0.size,
-                            );
+                        (block, ao)
+                    }));
+                }
+            }
+
+            for v in 0..v_size {
+                let mut u = 0;
+                while u < u_size {
+                    let Some((block, ao)) = mask[u + v * u_size] else {
+                        u += 1;
+                        continue;
+                    };
+
+                    // Grow the quad along u as long as the faces match.
+                    let mut width = 1;
+                    while u + width < u_size && mask[u + width + v * u_size] == Some((block, ao)) {
+                        width += 1;
+                    }
+
+                    // Then along v, as long as the whole row matches.
+                    let mut height = 1;
+                    while v + height < v_size
+                        && (u..u + width)
+                            .all(|row_u| mask[row_u + (v + height) * u_size] == Some((block, ao)))
+                    {
+                        height += 1;
+                    }
+
+                    for quad_v in v..v + height {
+                        for quad_u in u..u + width {
+                            mask[quad_u + quad_v * u_size] = None;
                         }
-                    } else {
-                        // Get the block type of the neighbor block in the current chunk.
-                        let neighbor_block_type = chunk_blocks[neighbor_x as usize]
-                            [neighbor_y as usize][neighbor_z as usize];
-                        // If the neighbor block is Air, we need to create a face.
-                        if neighbor_block_type == BlockType::Air
-                            || (block_type != BlockType::Water
-                                && neighbor_block_type == BlockType::Water)
-                            || (block_type != BlockType::Lava
-                                && neighbor_block_type == BlockType::Lava)
-                        {
-                            // Create the face.
-                            create_face(
-                                &mut vertices,
-                                &mut indices,
-                                &mut normals,
-                                &mut uvs,
-                                IVec2XZ::new(chunk_position.x, chunk_position.z),
-                                [x as f32, y as f32, z as f32],
-                                face,
-                                block_type,
-                                &game_texture.0.textures,
-                                &game_texture.0.size,
-                            );
+                    }
+                    if width * height < min_face_blocks {
+                        u += width;
+                        continue;
+                    }
+
+                    // Push the face of the first block, then stretch its far corners over the whole quad.
+                    let first_vertex = vertices.len();
+                    let position =
+                        block_world_position(chunk_position, dims, local_pos(depth, u, v));
+                    push_face(
+                        &mut vertices,
+                        &mut indices,
+                        &mut normals,
+                        &mut uvs,
+                        position,
+                        face,
+                        block,
+                        registry,
+                    );
+
+                    for vertex in &mut vertices[first_vertex..] {
+                        for (axis, quad_size) in [(u_axis, width), (v_axis, height)] {
+                            if vertex[axis] > position[axis] + 0.5 {
+                                vertex[axis] += quad_size as f32 - 1.;
+                            }
                         }
                     }
+
+                    // The first edge of a face runs along the texture's u, the second one along its v.
+                    let corners: Vec<Vec3> = vertices[first_vertex..]
+                        .iter()
+                        .map(|&vertex| Vec3::from_array(vertex))
+                        .collect();
+                    let uv_size = Vec2::new(
+                        corners[0].distance(corners[1]),
+                        corners[1].distance(corners[2]),
+                    );
+                    uvs.truncate(first_vertex);
+                    uvs.extend_from_slice(&[
+                        [0., 0.],
+                        [uv_size.x, 0.],
+                        [uv_size.x, uv_size.y],
+                        [0., uv_size.y],
+                    ]);
+                    push_ao(&mut colors, &mut indices, ao);
+                    layers.extend_from_slice(&[registry.get(block).textures.face(face); 4]);
+
+                    u += width;
                 }
             }
         }
     }
 
-    // Convert the vectors to VertexAttributeValues and add them to the mesh.
-    chunk_mesh.insert_attribute(
-        Mesh::ATTRIBUTE_POSITION,
-        VertexAttributeValues::Float32x3(vertices),
-    );
-    chunk_mesh.insert_attribute(
-        Mesh::ATTRIBUTE_NORMAL,
-        VertexAttributeValues::Float32x3(normals),
-    );
-    chunk_mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::Float32x2(uvs));
-    chunk_mesh.set_indices(Some(Indices::U32(indices)));
-
-    // Stop the timer
-    let elapsed = start.elapsed();
-    info!(
-        "Chunk generation @ x: {} z: {} took: {:?}",
-        chunk_position.x, chunk_position.z, elapsed
+    let mut mesh = ChunkMeshData {
+        vertices,
+        indices,
+        normals,
+        uvs,
+        colors,
+    }
+    .into_mesh();
+    mesh.insert_attribute(ATTRIBUTE_TEXTURE_LAYER, layers);
+
+    mesh
+}
+
+/// Checks if the face of the block at the local position in the chunk is visible, see [`face_visible`].
+fn face_exposed(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    local_pos: IVec3,
+    face: BlockFace,
+    neighbor_block: &impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+) -> bool {
+    let Some(block_type) = try_block(chunk_blocks, local_pos, dims) else {
+        return false;
+    };
+    let neighbor = local_block(
+        chunk_blocks,
+        chunk_position,
+        dims,
+        local_pos + face.offset(),
+        neighbor_block,
     );
 
-    chunk_mesh
+    match neighbor {
+        Some(neighbor) => face_visible(block_type, neighbor, registry),
+        // Nothing exists below or above the world, so the face is always visible.
+        None => true,
+    }
+}
+
+/// Returns the block at a position relative to the chunk, or `None` below and above the world.
+///
+/// Blocks outside of the chunk are looked up with `neighbor_block`.
+fn local_block(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    local_pos: IVec3,
+    neighbor_block: &impl Fn(IVec3) -> BlockType,
+) -> Option<BlockType> {
+    if !(0..dims.y as i32).contains(&local_pos.y) {
+        return None;
+    }
+
+    // Anything else outside of the chunk is in another chunk.
+    try_block(chunk_blocks, local_pos, dims).or_else(|| {
+        Some(neighbor_block(
+            block_world_position(chunk_position, dims, local_pos).as_ivec3(),
+        ))
+    })
 }
 
-/// Creates a face on a block.
+/// Returns the ambient occlusion of the corners of a face, from 0 (darkest) to 3 (not occluded).
+///
+/// `corners` are the corners of the face relative to the block, like [`push_face`] creates them. Each corner is
+/// darkened by the two blocks next to it and the block diagonal to it, in the layer the face looks at.
+pub fn face_ao(
+    local_pos: IVec3,
+    face: BlockFace,
+    corners: &[[f32; 3]],
+    occludes: impl Fn(IVec3) -> bool,
+) -> [u8; 4] {
+    let (u_axis, v_axis) = ((face.axis() + 1) % 3, (face.axis() + 2) % 3);
+    let in_front = local_pos + face.offset();
+
+    let mut ao = [3; 4];
+    for (corner, corner_ao) in corners.iter().zip(&mut ao) {
+        // Step from the center of the face towards the corner on both axes of the face.
+        let mut side1 = IVec3::ZERO;
+        let mut side2 = IVec3::ZERO;
+        side1[u_axis] = if corner[u_axis] > 0.5 { 1 } else { -1 };
+        side2[v_axis] = if corner[v_axis] > 0.5 { 1 } else { -1 };
+
+        let diagonal = occludes(in_front + side1 + side2);
+        let side1 = occludes(in_front + side1);
+        let side2 = occludes(in_front + side2);
+
+        // With both sides blocked, the corner is fully hidden no matter what's diagonal to it.
+        *corner_ao = if side1 && side2 {
+            0
+        } else {
+            3 - side1 as u8 - side2 as u8 - diagonal as u8
+        };
+    }
+
+    ao
+}
+
+/// Returns the [`face_ao`] of a block face in the chunk, where every opaque block occludes.
+#[allow(clippy::too_many_arguments)]
+fn chunk_face_ao(
+    chunk_blocks: &[BlockType],
+    chunk_position: IVec2XZ,
+    dims: ChunkDims,
+    local_pos: IVec3,
+    face: BlockFace,
+    block: BlockType,
+    neighbor_block: &impl Fn(IVec3) -> BlockType,
+    registry: &BlockRegistry,
+) -> [u8; 4] {
+    face_ao(local_pos, face, &face_corners(face, block), |pos| {
+        local_block(chunk_blocks, chunk_position, dims, pos, neighbor_block)
+            .is_some_and(|block| !registry.get(block).transparent)
+    })
+}
+
+/// Adds the vertex colors of the face that was just pushed, darkened by its ambient occlusion.
+///
+/// The face is split along its brighter diagonal, so the darkening doesn't look different on both triangles.
+fn push_ao(colors: &mut Vec<[f32; 4]>, indices: &mut [u32], ao: [u8; 4]) {
+    colors.extend(ao.map(|level| {
+        let brightness = AO_BRIGHTNESS[level as usize];
+        [brightness, brightness, brightness, 1.]
+    }));
+
+    if ao[0] + ao[2] < ao[1] + ao[3] {
+        let face_indices = indices.len() - 6..;
+        let first = indices[face_indices.start];
+        indices[face_indices].copy_from_slice(&[
+            first + 1,
+            first + 2,
+            first + 3,
+            first + 1,
+            first + 3,
+            first,
+        ]);
+    }
+}
+
+/// Offsets a position inside the chunk by the chunk position.
+fn block_world_position(chunk_position: IVec2XZ, dims: ChunkDims, local_pos: IVec3) -> Vec3 {
+    chunk_local_to_world(chunk_position, local_pos, dims).as_vec3()
+}
+
+/// Checks if the face of `block` that touches `neighbor` should be drawn.
+///
+/// Faces are drawn towards transparent blocks like air and liquids, unless the neighbor is the same block.
+/// Two blocks of water next to each other don't draw the face between them.
+///
+/// Translucent blocks only draw their faces towards air, so the water is just its surface and the walls where it
+/// meets the air. Anything else under the water can be seen through it anyway.
+pub fn face_visible(block: BlockType, neighbor: BlockType, registry: &BlockRegistry) -> bool {
+    if registry.get(block).translucent {
+        return neighbor == BlockType::Air;
+    }
+
+    registry.get(neighbor).transparent && neighbor != block
+}
+
+/// Appends one face of the block at `pos` (its world position) to the mesh vectors.
+///
+/// This adds the four corners, the outward normal, the UVs of the block texture and the two triangles of the face.
 #[allow(clippy::too_many_arguments)] // too lazy to fix
-fn create_face(
+pub fn push_face(
     vertices: &mut Vec<[f32; 3]>,
     indices: &mut Vec<u32>,
     normals: &mut Vec<[f32; 3]>,
     uvs: &mut Vec<[f32; 2]>,
-    chunk_position: IVec2XZ,
-    position: [f32; 3],
+    pos: Vec3,
     direction: BlockFace,
     block: BlockType,
-    textures: &[Rect],
-    size: &Vec2,
+    registry: &BlockRegistry,
 ) {
-    // Offset the position of the face based on the chunk position.
-    let position = [
-        position[0] + chunk_position.x as f32 * CHUNK_SIZE as f32,
-        position[1],
-        position[2] + chunk_position.z as f32 * CHUNK_SIZE as f32,
-    ];
-
     // Get the len of the vertices
     let vertices_len = vertices.len() as u32;
 
     // The normal of the face.
-    let normal = match direction {
-        BlockFace::Top => [0.0, 1.0, 0.0],
-        BlockFace::Bottom => [0.0, -1.0, 0.0],
-        BlockFace::Left => [-1.0, 0.0, 0.0],
-        BlockFace::Right => [1.0, 0.0, 0.0],
-        BlockFace::Front => [0.0, 0.0, 1.0],
-        BlockFace::Back => [0.0, 0.0, -1.0],
-    };
+    let normal = direction.normal();
 
     // The vertices of the face.
-    // Bevy has backface culling enabled by default. This means that the vertices need to be in clockwise order. If a face is not showing up, this is probably the reason. (this took me so long)
-    let face_vertices = match direction {
+    let face_vertices =
+        face_corners(direction, block).map(|corner| (pos + Vec3::from_array(corner)).to_array());
+
+    // Add the vertices and normals to the vectors.
+    vertices.extend_from_slice(&face_vertices);
+    normals.extend_from_slice(&[normal; 4]);
+
+    let uv = face_uv(block, direction, registry);
+
+    // Add the UV coordinates to the vector.
+    uvs.extend_from_slice(&uv);
+
+    // Add the indices to the vector. This is counter-clockwise order.
+    indices.extend_from_slice(&[
+        vertices_len,
+        vertices_len + 1,
+        vertices_len + 2,
+        vertices_len,
+        vertices_len + 2,
+        vertices_len + 3,
+    ]);
+}
+
+/// Returns the corners of a block face relative to the block, in the order they are added to the mesh.
+pub fn face_corners(direction: BlockFace, block: BlockType) -> [[f32; 3]; 4] {
+    // Bevy has backface culling enabled by default. This means that the vertices need to be in counter-clockwise order when looking at the face from outside the block. If a face is not showing up, this is probably the reason. (this took me so long)
+    match direction {
         BlockFace::Top => {
             // If this is water or lava and the face is the top, the top vert should be offset down by 0.1
             if BlockType::Water == block || BlockType::Lava == block {
                 [
-                    [position[0], position[1] + 0.9, position[2]],
-                    [position[0], position[1] + 0.9, position[2] + 1.0],
-                    [position[0] + 1.0, position[1] + 0.9, position[2] + 1.0],
-                    [position[0] + 1.0, position[1] + 0.9, position[2]],
+                    [0.0, 0.9, 0.0],
+                    [0.0, 0.9, 1.0],
+                    [1.0, 0.9, 1.0],
+                    [1.0, 0.9, 0.0],
                 ]
             } else {
                 [
-                    [position[0], position[1] + 1.0, position[2]],
-                    [position[0], position[1] + 1.0, position[2] + 1.0],
-                    [position[0] + 1.0, position[1] + 1.0, position[2] + 1.0],
-                    [position[0] + 1.0, position[1] + 1.0, position[2]],
+                    [0.0, 1.0, 0.0],
+                    [0.0, 1.0, 1.0],
+                    [1.0, 1.0, 1.0],
+                    [1.0, 1.0, 0.0],
                 ]
             }
         }
         BlockFace::Bottom => [
-            [position[0], position[1], position[2] + 1.0],
-            [position[0], position[1], position[2]],
-            [position[0] + 1.0, position[1], position[2]],
-            [position[0] + 1.0, position[1], position[2] + 1.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 1.0],
         ],
 
         BlockFace::Left => [
-            [position[0], position[1] + 1.0, position[2] + 1.0],
-            [position[0], position[1] + 1.0, position[2]],
-            [position[0], position[1], position[2]],
-            [position[0], position[1], position[2] + 1.0],
+            [0.0, 1.0, 1.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
         ],
         BlockFace::Right => [
-            [position[0] + 1.0, position[1] + 1.0, position[2]],
-            [position[0] + 1.0, position[1] + 1.0, position[2] + 1.0],
-            [position[0] + 1.0, position[1], position[2] + 1.0],
-            [position[0] + 1.0, position[1], position[2]],
+            [1.0, 1.0, 0.0],
+            [1.0, 1.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0],
         ],
         BlockFace::Front => [
-            [position[0] + 1.0, position[1] + 1.0, position[2] + 1.0],
-            [position[0], position[1] + 1.0, position[2] + 1.0],
-            [position[0], position[1], position[2] + 1.0],
-            [position[0] + 1.0, position[1], position[2] + 1.0],
+            [1.0, 1.0, 1.0],
+            [0.0, 1.0, 1.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
         ],
         BlockFace::Back => [
-            [position[0], position[1] + 1.0, position[2]],
-            [position[0] + 1.0, position[1] + 1.0, position[2]],
-            [position[0] + 1.0, position[1], position[2]],
-            [position[0], position[1], position[2]],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0],
         ],
-    };
-
-    // Add the vertices and normals to the vectors.
-    vertices.extend_from_slice(&face_vertices);
-    normals.extend_from_slice(&[normal; 4]);
-
-    let texture = match block {
-        BlockType::Bedrock => textures[0],
-        BlockType::Stone => textures[1],
-        BlockType::Dirt => textures[2],
-        BlockType::Grass => {
-            if direction == BlockFace::Top {
-                textures[3]
-            } else if direction == BlockFace::Bottom {
-                textures[2]
-            } else {
-                textures[4]
-            }
-        },
-        BlockType::Log => {
-            if direction == BlockFace::Top || 
-            direction == BlockFace::Bottom {
-                textures[12]
-            } else {
-                textures[5]
-            }
-        },
-        BlockType::Lava => textures[21],
-        BlockType::Water => textures[22],
-        BlockType::DiamondOre => textures[15],
-        BlockType::RedstoneOre => textures[14],
-        BlockType::GoldOre => textures[9],
-        BlockType::IronOre => textures[8],
-        BlockType::CoalOre => textures[7],
-        BlockType::Sand => textures[10],
-        BlockType::Air => textures[0], // todo: make this not cringe
-    };
+    }
+}
 
-    let uv = [
-        [texture.min.x / size.x, texture.min.y / size.y],
-        [texture.max.x / size.x, texture.min.y / size.y],
-        [texture.max.x / size.x, texture.max.y / size.y],
-        [texture.min.x / size.x, texture.max.y / size.y],
-    ];
+/// Returns the UVs of the four corners of a block face in the texture atlas.
+///
+/// Some blocks use a different texture depending on the face, like grass with its green top.
+pub fn face_uv(block: BlockType, face: BlockFace, registry: &BlockRegistry) -> [[f32; 2]; 4] {
+    // The index of the tile in the atlas, row by row.
+    let texture = registry.get(block).textures.face(face);
 
-    // Add the UV coordinates to the vector.
-    uvs.extend_from_slice(&uv);
+    atlas_uv(texture % ATLAS_TILES, texture / ATLAS_TILES)
+}
 
-    // Add the indices to the vector. This is clockwise order.
-    indices.extend_from_slice(&[
-        vertices_len,
-        vertices_len + 1,
-        vertices_len + 2,
-        vertices_len,
-        vertices_len + 2,
-        vertices_len + 3,
-    ]);
+/// Returns the UVs of the four corners of a tile in the texture atlas.
+///
+/// The UVs are moved slightly into the tile, so the neighbouring tiles don't bleed into the edges of the faces.
+pub fn atlas_uv(tile_x: u32, tile_y: u32) -> [[f32; 2]; 4] {
+    let tile_size = 1. / ATLAS_TILES as f32;
+
+    let min = Vec2::new(tile_x as f32, tile_y as f32) + ATLAS_TILE_INSET;
+    let max = Vec2::new(tile_x as f32 + 1., tile_y as f32 + 1.) - ATLAS_TILE_INSET;
+    let (min, max) = (min * tile_size, max * tile_size);
+
+    [
+        [min.x, min.y],
+        [max.x, min.y],
+        [max.x, max.y],
+        [min.x, max.y],
+    ]
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn chunk_system(
     mut chunks_loaded: ResMut<ChunksLoaded>,
-    mut chunk_query: Query<(Entity, &ChunkMesh)>,
     mut commands: Commands,
     camera_query: Query<&Transform, With<Camera3d>>,
     generating: Res<Generating>,
-    game_atlas: Res<GameTextureAtlas>,
+    render_distance: Res<RenderDistance>,
+    generation_config: Res<GenerationConfig>,
+    block_registry: Res<BlockRegistry>,
+    modified_chunks: Res<ModifiedChunks>,
+    chunk_query: Query<&ChunkMesh>,
+    chunk_data: Query<&ChunkData>,
+    terrain_noise: Res<SharedTerrainNoise>,
+    mesh_buffer_pool: Res<MeshBufferPool>,
+    mut chunk_progress: ResMut<ChunkProgress>,
 ) {
     // Check if the world is generating.
     if !generating.0 {
         return;
     }
 
-    let task_pool = AsyncComputeTaskPool::get();
-
     // Check for differences between the chunks that are loaded and the chunks that should be loaded.
-    let mut chunks_to_load: HashSet<IVec2XZ> = HashSet::new();
+    let mut chunks_to_load: Vec<IVec2XZ> = Vec::new();
     let mut chunks_to_unload: HashSet<IVec2XZ> = HashSet::new();
 
     // Get the camera position.
     let camera_position = camera_query.single().translation;
 
     // Calculate the player's chunk position based on their world position.
-    let player_chunk_position = IVec2XZ::new(
-        (camera_position.x / CHUNK_SIZE as f32).floor() as i32,
-        (camera_position.z / CHUNK_SIZE as f32).floor() as i32,
-    );
+    let player_chunk_position =
+        IVec2XZ::from_world(camera_position, generation_config.chunk_dims());
 
     // Calculate the radius of the sphere around the player.
-    let radius = RENDER_DISTANCE;
+    let radius = render_distance.0;
 
     // Check for chunks to load in a circle.
+    let mut chunks_in_range = 0;
     for x in -radius..=radius {
         for z in -radius..=radius {
             // Check if the chunk position is within the circle.
             if x * x + z * z <= radius * radius {
                 let chunk_position = player_chunk_position + IVec2XZ::new(x, z);
 
+                // Chunks outside of the world grid are empty anyway.
+                if !chunk_position.in_world() {
+                    continue;
+                }
+                chunks_in_range += 1;
+
                 // Check if the chunk is already loaded.
-                if !chunks_loaded.chunks.contains(&chunk_position) {
+                if !chunks_loaded.chunks.contains_key(&chunk_position) {
                     // Chunk is not loaded, add it to the list of chunks to load.
-                    chunks_to_load.insert(chunk_position);
+                    chunks_to_load.push(chunk_position);
                 }
             }
         }
     }
 
     // Check for chunks to unload in a circle.
-    for loaded_chunk_position in chunks_loaded.chunks.iter() {
+    for loaded_chunk_position in chunks_loaded.chunks.keys() {
         let distance = *loaded_chunk_position - player_chunk_position;
 
         // Check if the chunk is outside the render distance.
@@ -374,305 +1555,706 @@ pub fn chunk_system(
         }
     }
 
-    // Load the chunks.
-    for chunk_position in chunks_to_load {
-        // Spawn a new task to generate chunk mesh.
-        let game_atlas = game_atlas.clone();
-        let task = task_pool.spawn(async move { create_chunk_mesh(chunk_position, game_atlas) });
+    chunk_progress.total = chunks_in_range;
 
-        // Add the task as a component to a new entity.
-        commands.spawn((
-            ComputeMeshTask(task),
-            ChunkMesh {
-                position: chunk_position,
-            },
-        ));
+    let chunk_lod = |chunk_position: IVec2XZ| {
+        let distance = chunk_position - player_chunk_position;
+        generation_config.meshing.lod(lod_for_distance(
+            ((distance.x * distance.x + distance.z * distance.z) as f32).sqrt(),
+            radius,
+        ))
+    };
+
+    // Load the closest chunks first, so the world builds outward from the camera. The rest wait for the next frames.
+    chunks_to_load.sort_by_key(|&chunk_position| {
+        let distance = chunk_position - player_chunk_position;
+        distance.x * distance.x + distance.z * distance.z
+    });
+    let chunks_per_frame = if generation_config.synchronous {
+        usize::MAX
+    } else {
+        CHUNK_TASKS_PER_FRAME
+    };
+    for chunk_position in chunks_to_load.into_iter().take(chunks_per_frame) {
+        let lod = chunk_lod(chunk_position);
 
-        chunks_loaded.chunks.insert(chunk_position);
+        // Spawn a new task to generate chunk mesh.
+        let task = chunk_mesh_task(
+            chunk_position,
+            lod,
+            *generation_config,
+            &block_registry,
+            &modified_chunks,
+            &terrain_noise,
+            &mesh_buffer_pool,
+        );
+
+        let entity = spawn_chunk(&mut commands, chunk_position, lod, task);
+        chunks_loaded.chunks.insert(chunk_position, entity);
+    }
+
+    // Re-mesh the chunks the camera moved closer to or further away from.
+    for chunk_mesh in chunk_query.iter() {
+        let lod = chunk_lod(chunk_mesh.position);
+        if lod != chunk_mesh.lod && !chunks_to_unload.contains(&chunk_mesh.position) {
+            remesh_chunk(
+                &mut commands,
+                chunk_mesh.position,
+                lod,
+                &chunks_loaded,
+                |entity| chunk_data.get(entity).ok(),
+                &modified_chunks,
+                *generation_config,
+                &block_registry,
+                &terrain_noise,
+                &mesh_buffer_pool,
+            );
+        }
     }
 
     // Unload the chunks.
     // info!("Unloading {} chunks", chunks_to_unload.len());
     for chunk_position in chunks_to_unload {
-        // Find the entity corresponding to the chunk.
-        for (entity, chunk_mesh) in chunk_query.iter_mut() {
-            if chunk_mesh.position == chunk_position {
-                // TODO: Make this async
-
-                // Remove the chunk from the loaded chunks.
-                chunks_loaded.chunks.retain(|&x| x != chunk_position);
-
-                // Despawn the chunk.
-                commands.entity(entity).despawn(); // TODO: Fix the warning if the chunk has been despawned already by another thread.
-
-                break;
-            }
+        // Remove the chunk from the loaded chunks.
+        if let Some(entity) = chunks_loaded.chunks.remove(&chunk_position) {
+            // Despawn the chunk. This drops its mesh handle, so the mesh is freed too.
+            commands.entity(entity).despawn_recursive();
         }
     }
 }
 
-pub fn handle_mesh_tasks(
-    mut commands: Commands,
-    mut mesh_tasks: Query<(Entity, &mut ComputeMeshTask)>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    game_atlas: Res<GameTextureAtlas>,
-    mut chunks_loaded: ResMut<ChunksLoaded>,
+/// Counts the loaded chunks and the running tasks for the [`ChunkProgress`]. The total is counted by [`chunk_system`].
+pub fn track_chunk_progress(
+    chunks_loaded: Res<ChunksLoaded>,
+    generated_query: Query<(), With<ChunkData>>,
+    task_query: Query<(), With<ComputeMeshTask>>,
+    mut chunk_progress: ResMut<ChunkProgress>,
 ) {
-    let texture = game_atlas.0.texture.clone_weak();
+    chunk_progress.loaded = chunks_loaded
+        .chunks
+        .values()
+        .filter(|&&entity| generated_query.contains(entity))
+        .count() as u32;
+    chunk_progress.meshing = task_query.iter().count() as u32;
+}
+
+/// Spawns the entity of a chunk that is still being generated by the `task`. Its mesh, material and collider are added
+/// by [`handle_mesh_tasks`] once the task is done, see [`insert_chunk_mesh`].
+pub fn spawn_chunk(
+    commands: &mut Commands,
+    chunk_position: IVec2XZ,
+    lod: usize,
+    task: ComputeMeshTask,
+) -> Entity {
+    // It needs a transform for the water mesh, which is its child.
+    commands
+        .spawn((
+            task,
+            SpatialBundle::default(),
+            ChunkMesh {
+                position: chunk_position,
+                lod,
+            },
+            ChunkStats::default(),
+        ))
+        .id()
+}
 
-    for (entity, mut task) in &mut mesh_tasks {
-        if let Some(chunk_mesh) = future::block_on(future::poll_once(&mut task.0)) {
-            let chunk_mesh_handle: Handle<Mesh> = meshes.add(chunk_mesh);
+/// Gives a chunk entity its mesh, drawn with the shared `material` and moved to the `translation`. The material of
+/// the other type is taken away, in case the chunk was meshed with another [`MeshingMode`] before.
+pub fn insert_chunk_mesh<M: Material>(
+    commands: &mut Commands,
+    entity: Entity,
+    meshes: &mut Assets<Mesh>,
+    mesh: Mesh,
+    material: Handle<M>,
+    translation: Vec3,
+) {
+    commands
+        .entity(entity)
+        .remove::<(Handle<StandardMaterial>, Handle<ChunkArrayMaterial>)>()
+        .insert(MaterialMeshBundle {
+            mesh: meshes.add(mesh),
+            material,
+            // Only moved if the vertices are relative to the chunk, see `ChunkMeshData`.
+            transform: Transform::from_translation(translation),
+            ..default()
+        });
+}
 
-            // Get the vertices and indices from the mesh. This is needed to create the collider.
-            let (vertices, indices) = get_verts_indices(meshes.get(&chunk_mesh_handle).unwrap());
+/// Returns the level of detail of a chunk `distance` chunks away from the camera. The closest third of the render
+/// distance is at full detail, the next third is meshed from every 2nd block and the rest from every 4th block.
+pub fn lod_for_distance(distance: f32, render_distance: i32) -> usize {
+    let third = render_distance as f32 / 3.;
+    if distance <= third {
+        1
+    } else if distance <= third * 2. {
+        2
+    } else {
+        4
+    }
+}
 
-            // Check if there are vertices in the mesh.
-            if vertices.is_empty() {
-                // Despawn the entity.
-                commands.entity(entity).despawn_recursive();
+/// Spawns a task on the async compute pool that generates the mesh of a chunk.
+#[allow(clippy::too_many_arguments)]
+fn chunk_mesh_task(
+    chunk_position: IVec2XZ,
+    lod: usize,
+    config: GenerationConfig,
+    registry: &BlockRegistry,
+    modified: &ModifiedChunks,
+    noise: &SharedTerrainNoise,
+    pool: &MeshBufferPool,
+) -> ComputeMeshTask {
+    let registry = registry.clone();
+    let modified = modified.around(chunk_position);
+    let noise = noise.get(&config);
+    let pool = pool.clone();
+    mesh_task(config.synchronous, move || {
+        generate_chunk(chunk_position, lod, config, registry, modified, noise, pool)
+    })
+}
 
-                break;
-            }
+/// Runs `generate` on the async compute pool, or right away on this thread if `synchronous`. Either way the chunk is
+/// picked up from the returned task by [`handle_mesh_tasks`], with [`GeneratedChunk::gen_time`] set.
+fn mesh_task(
+    synchronous: bool,
+    generate: impl FnOnce() -> GeneratedChunk + Send + 'static,
+) -> ComputeMeshTask {
+    let timed_generate = move || {
+        let start = Instant::now();
+        let mut generated_chunk = generate();
+        generated_chunk.gen_time = start.elapsed();
+        generated_chunk
+    };
 
-            // Check if every vertice is contained in at least one chunk from the chunks that are loaded.
-            // The vertices should be scaled by the chunk size.
+    let task_pool = AsyncComputeTaskPool::get();
+    let task = if synchronous {
+        let generated_chunk = timed_generate();
+        task_pool.spawn(async move { generated_chunk })
+    } else {
+        task_pool.spawn(async move { timed_generate() })
+    };
 
-            let chunk_position = IVec2XZ::new(
-                (vertices[0].x / CHUNK_SIZE as f32).floor() as i32,
-                (vertices[0].z / CHUNK_SIZE as f32).floor() as i32,
-            );
+    ComputeMeshTask { task, synchronous }
+}
 
-            // Check if this chunk position is even loaded
-            if !chunks_loaded.chunks.contains(&chunk_position) {
-                // warn!("Prevented crash!");
+/// Rebuilds the mesh of a loaded chunk from its [`ChunkData`], e.g. after a block in it was changed.
+///
+/// The old mesh stays until the new one is handled. Does nothing if the chunk isn't loaded or still generating. The
+/// blocks are only read, through `chunk_data`, so the callers don't need mutable access to every chunk.
+#[allow(clippy::too_many_arguments)]
+pub fn remesh_chunk<'a>(
+    commands: &mut Commands,
+    chunk_position: IVec2XZ,
+    lod: usize,
+    chunks_loaded: &ChunksLoaded,
+    chunk_data: impl Fn(Entity) -> Option<&'a ChunkData>,
+    modified: &ModifiedChunks,
+    config: GenerationConfig,
+    registry: &BlockRegistry,
+    noise: &SharedTerrainNoise,
+    pool: &MeshBufferPool,
+) {
+    let loaded_data = |position: IVec2XZ| {
+        let entity = *chunks_loaded.chunks.get(&position)?;
+        chunk_data(entity).map(|data| (entity, data))
+    };
 
-                // Despawn the entity.
-                commands.entity(entity).despawn_recursive();
+    let Some((entity, data)) = loaded_data(chunk_position) else {
+        return;
+    };
+    let chunk_blocks = data.0.clone();
 
-                // Remove the chunk from the loaded chunks.
-                chunks_loaded.chunks.retain(|&x| x != chunk_position);
+    // Take the edited neighbors into account, the noise doesn't know about them.
+    let mut neighbors = ChunkNeighbors::new();
+    for neighbor_position in chunk_position.neighbors() {
+        if let Some((_, neighbor_data)) = loaded_data(neighbor_position) {
+            neighbors.insert(neighbor_position, neighbor_data.0.clone());
+        }
+    }
 
-                break;
-            } else {
-                commands
-                    .entity(entity)
-                    .insert(PbrBundle {
-                        mesh: chunk_mesh_handle,
-                        material: materials.add(StandardMaterial {
-                            base_color_texture: Some(texture.clone()),
-                            metallic: 1.,
-                            reflectance: 1.,
-                            ..default()
-                        }),
-                        ..Default::default()
-                    })
-                    .insert(Collider::trimesh(vertices, indices));
+    let registry = registry.clone();
+    let modified = modified.around(chunk_position);
+    let noise = noise.get(&config);
+    let pool = pool.clone();
+    let task = mesh_task(config.synchronous, move || {
+        let mut buffers = pool.take();
+        let generated_chunk = mesh_chunk(
+            chunk_position,
+            lod,
+            &chunk_blocks,
+            &neighbors,
+            &modified,
+            &noise,
+            &config,
+            &registry,
+            &mut buffers,
+        );
+        pool.give(buffers);
+        generated_chunk
+    });
+
+    commands.entity(entity).insert((
+        task,
+        ChunkMesh {
+            position: chunk_position,
+            lod,
+        },
+    ));
+}
 
-                // Task is complete, so remove task component from entity
-                commands.entity(entity).remove::<ComputeMeshTask>();
+/// Re-meshes the chunks marked with [`DirtyChunk`] at their current level of detail, and clears the mark.
+///
+/// A chunk edited several times in a frame is only re-meshed once, and the chunks that didn't change are left alone.
+/// Chunks with [`ChunkFaces`] only get the faces around the [`BlockEdits`] patched, if that's enough. The edits are
+/// cleared every frame, so this has to run after the systems that edit blocks, once their marks are applied.
+#[allow(clippy::type_complexity)]
+pub fn remesh_dirty_chunks(
+    mut commands: Commands,
+    mut dirty_query: Query<
+        (
+            Entity,
+            &ChunkMesh,
+            Option<&mut ChunkFaces>,
+            Option<&Handle<Mesh>>,
+            Option<&mut ChunkStats>,
+            Has<ComputeMeshTask>,
+        ),
+        With<DirtyChunk>,
+    >,
+    mut world: VoxelWorld,
+    mut meshes: ResMut<Assets<Mesh>>,
+    block_registry: Res<BlockRegistry>,
+    mesh_buffer_pool: Res<MeshBufferPool>,
+) {
+    let edits = std::mem::take(&mut world.edits.0);
+    let dims = world.config.chunk_dims();
+
+    for (entity, chunk_mesh, faces, mesh_handle, stats, meshing) in &mut dirty_query {
+        commands.entity(entity).remove::<DirtyChunk>();
+
+        let chunk_edits: Vec<(IVec3, BlockType)> = edits
+            .iter()
+            .filter(|&&(pos, _)| IVec2XZ::from_block(pos, dims) == chunk_mesh.position)
+            .map(|&(pos, old_block)| (world_to_local(pos, dims), old_block))
+            .collect();
+        // A task that is still meshing was started before the edit, so its mesh has to be replaced anyway.
+        if let (Some(mut faces), Some(mesh), false) = (
+            faces,
+            mesh_handle.and_then(|handle| meshes.get_mut(handle)),
+            meshing,
+        ) {
+            if patch_edited_chunk(
+                &mut commands,
+                entity,
+                chunk_mesh.position,
+                &chunk_edits,
+                &mut faces,
+                mesh,
+                stats,
+                &world,
+                &block_registry,
+            ) {
+                continue;
             }
         }
+
+        remesh_chunk(
+            &mut commands,
+            chunk_mesh.position,
+            chunk_mesh.lod,
+            &world.chunks_loaded,
+            |entity| world.chunk_data.get(entity).ok(),
+            &world.modified,
+            *world.config,
+            &block_registry,
+            &world.noise,
+            &mesh_buffer_pool,
+        );
     }
 }
 
-fn surface_generation(pos: IVec3, perlin: &Perlin) -> BlockType {
-    // Sample the noise function at the scaled position.
-    // The perlin noise needs a float value, so we need to cast the scaled position to a float.
+/// Patches the faces around the `edits` into the mesh of a chunk with [`patch_chunk_mesh`], and rebuilds its
+/// collider. The edits are relative to the chunk, with the block that was there before.
+///
+/// Returns `false` if the chunk has to be re-meshed instead. That's the case for edits on the chunk border, which
+/// change the mesh of the next chunk too, and for edits involving glowing or translucent blocks, whose lights and
+/// water meshes aren't patched.
+#[allow(clippy::too_many_arguments)]
+fn patch_edited_chunk(
+    commands: &mut Commands,
+    entity: Entity,
+    chunk_position: IVec2XZ,
+    edits: &[(IVec3, BlockType)],
+    faces: &mut ChunkFaces,
+    mesh: &mut Mesh,
+    stats: Option<Mut<ChunkStats>>,
+    world: &VoxelWorld,
+    registry: &BlockRegistry,
+) -> bool {
+    let start = Instant::now();
+    let dims = world.config.chunk_dims();
+    let Ok(chunk_data) = world.chunk_data.get(entity) else {
+        return false;
+    };
+    let chunk_blocks = &chunk_data.0;
+    let special = |block: BlockType| {
+        let info = registry.get(block);
+        info.translucent || info.emissive != Color::BLACK
+    };
 
-    // 2d perlin noise
-    // let noise_value = perlin.get([pos.x as f64 * SURFACE_SCALE, pos.z as f64 * SURFACE_SCALE]);
-    // to make the terrain even more interesting, we can add more octaves of noise
+    let on_border = |pos: IVec3| {
+        pos.x <= 0 || pos.z <= 0 || pos.x >= dims.x as i32 - 1 || pos.z >= dims.z as i32 - 1
+    };
+    let near_water = |pos: IVec3| {
+        (pos.y - 1..=pos.y + 1)
+            .flat_map(|y| {
+                (pos.x - 1..=pos.x + 1)
+                    .flat_map(move |x| (pos.z - 1..=pos.z + 1).map(move |z| IVec3::new(x, y, z)))
+            })
+            .filter_map(|around| try_block(chunk_blocks, around, dims))
+            .any(|block| registry.get(block).translucent)
+    };
+    if edits.is_empty()
+        || edits.iter().any(|&(pos, old_block)| {
+            on_border(pos)
+                || special(old_block)
+                || try_block(chunk_blocks, pos, dims).map_or(true, special)
+                || near_water(pos)
+        })
+    {
+        return false;
+    }
 
-    let noise_values = vec![
-        perlin.get([
-            pos.x as f64 * 2. * SURFACE_SCALE,
-            pos.z as f64 * 2. * SURFACE_SCALE,
-        ]),
-        perlin.get([
-            pos.x as f64 * 4. * SURFACE_SCALE,
-            pos.z as f64 * 4. * SURFACE_SCALE,
-        ]),
-        perlin.get([
-            pos.x as f64 * 6. * SURFACE_SCALE,
-            pos.z as f64 * 6. * SURFACE_SCALE,
-        ]),
-    ];
-    // add all the noise values together
-    let noise_value = noise_values.iter().fold(0., |acc, &x| acc + x);
-    // let noise_value32 = noise_value as f32;
-
-    // Change values (-1, 1) -> (TERRAIN_HEIGHT, MAX_HEIGHT)
-    let cieling_margin = 100; // 140 blocks from height limit
-    let max_height = CHUNK_HEIGHT - cieling_margin;
-    let height = remap(
-        noise_value as f32,
-        -1., //-1.
-        6., //1.
-        BLEND_HEIGHT as f32,
-        max_height as f32,
-    );
+    let Some(mut data) = ChunkMeshData::from_mesh(mesh) else {
+        return false;
+    };
+    let translation = if world.config.local_space {
+        chunk_position.origin(dims).as_vec3()
+    } else {
+        Vec3::ZERO
+    };
+    let edited: Vec<IVec3> = edits.iter().map(|&(pos, _)| pos).collect();
+    let quads_before = faces.0.len() as u32;
+    if !patch_chunk_mesh(
+        &mut data,
+        &mut faces.0,
+        &edited,
+        chunk_blocks,
+        chunk_position,
+        dims,
+        |pos| world.get_block(pos),
+        registry,
+        translation,
+    ) {
+        return false;
+    }
+    let quads_after = faces.0.len() as u32;
+    *mesh = data.into_mesh();
 
-    // calculate block type given block position and height
-    match pos.y {
-        y if y == 0 => BlockType::Bedrock,
-        // y if y + 3 < height as i32 => BlockType::Stone,
-        y if y + 3 < height as i32 => cave_block(pos),
-        y if y < height as i32 && !(y > 63 && y < 72) && y > 64 => BlockType::Dirt,
-        y if y == height as i32 && !(y > 63 && y < 72) && y > 64 => BlockType::Grass,
-        // y if y <= height as i32 && y == 6 => cave_generation(pos, perlin, true),
-        y if !y <= height as i32 && y < 64 => BlockType::Stone,
-        y if y <= height as i32 && (y > 63 && y < 72) => BlockType::Sand,
-        y if !y <= height as i32 && y == 64 => BlockType::Sand,
-        // y if y > 64 && y <= WATER_HEIGHT as i32 => BlockType::Water,
-        y if !y <= height as i32 && y <= WATER_HEIGHT as i32 => BlockType::Water,
-        _ => BlockType::Air,
-    }
-}
-
-fn cave_generation(pos: IVec3, perlin: &Perlin) -> BlockType {
-    // Sample the noise function at the scaled position.
-    // The perlin noise needs a float value, so we need to cast the scaled position to a float.
-
-    // 3d perlin noise
-    let cave_noise_value = perlin.get([
-        pos.x as f64 * CAVE_SCALE,
-        pos.y as f64 * CAVE_SCALE,
-        pos.z as f64 * CAVE_SCALE,
-    ]);
+    let (vertices, indices) = get_verts_indices(mesh);
+    if vertices.is_empty() {
+        // Rare enough to leave to the full re-mesh, which also takes the empty chunk's mesh away.
+        return false;
+    }
+    commands.entity(entity).insert(Collider::trimesh(vertices, indices));
+    if let Some(aabb) = mesh.compute_aabb() {
+        commands.entity(entity).insert(aabb);
+    }
+    if let Some(mut stats) = stats {
+        stats.air = chunk_blocks.iter().filter(|&&block| block == BlockType::Air).count() as u32;
+        stats.solid = chunk_blocks.iter().filter(|&&block| registry.get(block).solid).count() as u32;
+        stats.faces = (stats.faces + quads_after).saturating_sub(quads_before);
+        stats.vertices = (stats.vertices + quads_after * 4).saturating_sub(quads_before * 4);
+    }
 
+    debug!("Patched chunk {:?} in {:?}", chunk_position, start.elapsed());
+    true
+}
 
-    // //
-    let noise_values = vec![
-        perlin.get([
-            pos.x as f64 * 2. * SURFACE_SCALE,
-            pos.z as f64 * 2. * SURFACE_SCALE,
-        ]),
-        perlin.get([
-            pos.x as f64 * 4. * SURFACE_SCALE,
-            pos.z as f64 * 4. * SURFACE_SCALE,
-        ]),
-        perlin.get([
-            pos.x as f64 * 6. * SURFACE_SCALE,
-            pos.z as f64 * 6. * SURFACE_SCALE,
-        ]),
-    ];
-    // add all the noise values together
-    let noise_value = noise_values.iter().fold(0., |acc, &x| acc + x);
-    // let noise_value32 = noise_value as f32;
-
-    // Change values (-1, 1) -> (TERRAIN_HEIGHT, MAX_HEIGHT)
-    let cieling_margin = 100; // 140 blocks from height limit
-    let max_height = CHUNK_HEIGHT - cieling_margin;
-    let height = remap(
-        noise_value as f32,
-        -1., //-1.
-        6., //1.
-        BLEND_HEIGHT as f32,
-        max_height as f32,
-    );
+/// Rebuilds the [`SharedTerrainNoise`] when the [`GenerationConfig`] needs a different noise, like after a seed change.
+pub fn update_terrain_noise(
+    generation_config: Res<GenerationConfig>,
+    mut terrain_noise: ResMut<SharedTerrainNoise>,
+) {
+    if generation_config.is_changed() {
+        terrain_noise.update(&generation_config);
+    }
+}
 
-    let no_ocean: bool = pos.y + 10 < height as i32;
-    // //
+/// Regenerates every loaded chunk when the [`GenerationConfig`] changes.
+///
+/// The chunks are only rebuilt once the config stopped changing for a moment, so dragging a slider in the inspector doesn't regenerate the world every frame.
+#[allow(clippy::too_many_arguments)]
+pub fn regenerate_on_config_change(
+    mut commands: Commands,
+    chunk_query: Query<(Entity, &ChunkMesh)>,
+    generation_config: Res<GenerationConfig>,
+    block_registry: Res<BlockRegistry>,
+    mut chunks_loaded: ResMut<ChunksLoaded>,
+    mut modified_chunks: ResMut<ModifiedChunks>,
+    terrain_noise: Res<SharedTerrainNoise>,
+    mesh_buffer_pool: Res<MeshBufferPool>,
+    time: Res<Time>,
+    mut debounce: Local<Option<Timer>>,
+    mut last_chunk_dims: Local<Option<ChunkDims>>,
+) {
+    // Restart the countdown every time the config changes.
+    if generation_config.is_changed() && !generation_config.is_added() {
+        *debounce = Some(Timer::from_seconds(REGENERATE_DELAY, TimerMode::Once));
+    }
 
-    if cave_noise_value < CAVE_THRESHOLD || !no_ocean {
-        if !(cave_noise_value < CAVE_THRESHOLD || pos.y > 62 && pos.y < 70) {
-            BlockType::Air
-        } else {
-            cave_block(pos)
+    // Chunk positions and block indices mean something else with another chunk size, so start over right away.
+    let dims = generation_config.chunk_dims();
+    if last_chunk_dims
+        .replace(dims)
+        .is_some_and(|last_dims| last_dims != dims)
+    {
+        info!("Chunk size changed to {}, reloading chunks", dims);
+
+        for (entity, _) in chunk_query.iter() {
+            commands.entity(entity).despawn_recursive();
         }
-    } else {
-        BlockType::Air
+        chunks_loaded.chunks.clear();
+        // The edits are stored by block index, so they are moved into the new chunks. Edits loaded from a save
+        // together with the new size are already for it.
+        modified_chunks.resize(dims);
+        *debounce = None;
+
+        return;
+    }
+
+    let Some(timer) = debounce.as_mut() else {
+        return;
+    };
+    if !timer.tick(time.delta()).finished() {
+        return;
+    }
+    *debounce = None;
+
+    info!("Generation config changed, regenerating chunks");
+
+    // The new mesh replaces the old one once the task is handled, so the chunks don't disappear in the meantime.
+    for (entity, chunk_mesh) in chunk_query.iter() {
+        let task = chunk_mesh_task(
+            chunk_mesh.position,
+            chunk_mesh.lod,
+            *generation_config,
+            &block_registry,
+            &modified_chunks,
+            &terrain_noise,
+            &mesh_buffer_pool,
+        );
+        commands.entity(entity).insert(task);
     }
 }
 
-fn cave_block(pos: IVec3) -> BlockType {
-    let ore_perlin = Perlin::new(SEED);
-    let noise_ore_generation = ore_perlin.get([
-        pos.x as f64 * ORE_SCALE,
-        pos.y as f64 * ORE_SCALE,
-        pos.z as f64 * ORE_SCALE,
-    ]);
+/// Creates the [`BlockMaterials`] from the [`BlockInfo`] of every block type.
+pub fn setup_block_materials(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    block_registry: Res<BlockRegistry>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // The same handle as the one of the `GameTextureAtlas`.
+    let texture = asset_server.load("textures/blocks.png");
+
+    let block_materials = BlockType::ALL
+        .into_iter()
+        .filter(|&block| block != BlockType::Air)
+        .map(|block| {
+            let info = block_registry.get(block);
+            let material = StandardMaterial {
+                base_color: if info.translucent {
+                    Color::rgba(1., 1., 1., WATER_ALPHA)
+                } else {
+                    Color::WHITE
+                },
+                base_color_texture: Some(texture.clone()),
+                perceptual_roughness: info.roughness,
+                metallic: info.metallic,
+                emissive: info.emissive,
+                alpha_mode: if info.translucent {
+                    AlphaMode::Blend
+                } else {
+                    AlphaMode::Opaque
+                },
+                ..default()
+            };
+            (block, materials.add(material))
+        })
+        .collect();
+
+    commands.insert_resource(BlockMaterials(block_materials));
+}
 
-    // Check if the noise value is above the threshhold
-    // if (0.01..0.9).contains(&noise_bedrock_generation) && pos.y <= 4 {
-    if DIAMOND_THRESHOLD.contains(&noise_ore_generation) && pos.y <= 16 {
-        BlockType::DiamondOre
-    } else if REDSTONE_THRESHOLD.contains(&noise_ore_generation) && pos.y <= 28 && pos.y >= 6 {
-        BlockType::RedstoneOre
-    } else if GOLD_THRESHOLD.contains(&noise_ore_generation) && pos.y <= 24 && pos.y >= 6 {
-        BlockType::GoldOre
-    } else if IRON_THRESHOLD.contains(&noise_ore_generation) && pos.y <= 70 && pos.y >= 8 {
-        BlockType::IronOre
-    } else if COAL_THRESHOLD.contains(&noise_ore_generation) && pos.y <= 78 && pos.y >= 24 {
-        BlockType::CoalOre
-    } else {
-        BlockType::Stone
-    }
+/// Creates the [`ChunkMaterials`] once, every chunk gets a handle to them. Needs the [`EmissiveAtlas`].
+pub fn setup_chunk_materials(
+    mut commands: Commands,
+    game_atlas: Res<GameTextureAtlas>,
+    emissive_atlas: Res<EmissiveAtlas>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let texture = game_atlas.0.texture.clone();
+
+    let terrain = materials.add(StandardMaterial {
+        base_color_texture: Some(texture.clone()),
+        // Multiplied with the emissive texture, so only the tiles of glowing blocks glow.
+        emissive: Color::WHITE,
+        emissive_texture: Some(emissive_atlas.0.clone()),
+        metallic: 1.,
+        reflectance: 1.,
+        ..default()
+    });
+    let water = materials.add(StandardMaterial {
+        base_color: Color::rgba(1., 1., 1., WATER_ALPHA),
+        base_color_texture: Some(texture),
+        alpha_mode: AlphaMode::Blend,
+        ..default()
+    });
+
+    commands.insert_resource(ChunkMaterials { terrain, water });
 }
 
-fn is_block(pos: IVec3, perlin: &Perlin) -> BlockType {
-    // is blocks
+pub fn handle_mesh_tasks(
+    mut commands: Commands,
+    mut mesh_tasks: Query<(Entity, &ChunkMesh, &mut ComputeMeshTask)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    chunk_materials: Res<ChunkMaterials>,
+    chunk_textures: Res<ChunkTextures>,
+    block_materials: Res<BlockMaterials>,
+    chunks_loaded: Res<ChunksLoaded>,
+) {
+    for (entity, chunk_mesh, mut task) in &mut mesh_tasks {
+        // The synchronous chunks are already generated, but the task pool may not have handed them back yet.
+        let generated_chunk = if task.synchronous {
+            future::block_on(&mut task.task)
+        } else {
+            let Some(generated_chunk) = future::block_on(future::poll_once(&mut task.task)) else {
+                continue;
+            };
+            generated_chunk
+        };
 
-    // limit the world size because it will start breaking at extreme distances
-    let no_border = true;
-    let border = 128; // i32::MAX
-    if (pos.x >= border || pos.x < -border || pos.z >= border || pos.z < -border) && !no_border {
-        return BlockType::Air;
-    }
+        // Check if this chunk position is even loaded
+        if chunks_loaded.chunks.get(&chunk_mesh.position) != Some(&entity) {
+            // warn!("Prevented crash!");
 
-    // Limit the world sky
-    if pos.y >= 255 {
-        return BlockType::Air;
-    }
+            // Despawn the entity.
+            commands.entity(entity).despawn_recursive();
 
-    // Set bedrock
-    if pos.y == 0 {
-        return BlockType::Bedrock;
-    }
+            continue;
+        }
 
-    // // Tests
-    // if pos.y == 0 {
-    //     return BlockType::Bedrock;
-    // } else {
-    //     let surface_block = surface_generation(pos, perlin);
-    //     surface_block
-    // }
+        // Task is complete, so remove task component from entity
+        commands.entity(entity).remove::<ComputeMeshTask>();
 
-    // Generate the 2d surface block. If it's a block, check if a cave should be generated.
-    // Lava on air blocks below
-    let surface_block = surface_generation(pos, perlin);
-    if surface_block != BlockType::Air {
-        let cave_block = cave_generation(pos, perlin);
-        if cave_block == BlockType::Air {
-            if pos.y <= LAVA_HEIGHT as i32 {
-                BlockType::Lava
-            } else {
-                // TODO: Don't spawn caves between BLEND_HEIGHT and below WATER_HEIGHT
-                cave_block
-            }
-        } else {
-            surface_block
+        if let Some(blocks) = generated_chunk.blocks {
+            commands.entity(entity).insert(ChunkData(blocks));
+        }
+        commands
+            .entity(entity)
+            .insert((generated_chunk.stats, GenTime(generated_chunk.gen_time)));
+        match generated_chunk.faces {
+            Some(faces) => commands.entity(entity).insert(ChunkFaces(faces)),
+            None => commands.entity(entity).remove::<ChunkFaces>(),
+        };
+
+        // Replace the water and the lights of the old mesh.
+        commands.entity(entity).despawn_descendants();
+        if let Some(water_mesh) = generated_chunk.water_mesh {
+            let water = commands
+                .spawn((
+                    PbrBundle {
+                        mesh: meshes.add(water_mesh),
+                        material: chunk_materials.water.clone(),
+                        ..default()
+                    },
+                    ChunkWater,
+                    NotShadowCaster,
+                ))
+                .id();
+            commands.entity(entity).add_child(water);
         }
-    } else {
-        surface_block
-    }
 
-}
+        // Every block type is its own child, which follows the transform of the chunk.
+        let per_block = !generated_chunk.block_meshes.is_empty();
+        for (block, block_mesh) in generated_chunk.block_meshes {
+            let Some(material) = block_materials.0.get(&block) else {
+                continue;
+            };
+            let child = commands
+                .spawn(PbrBundle {
+                    mesh: meshes.add(block_mesh),
+                    material: material.clone(),
+                    ..default()
+                })
+                .id();
+            commands.entity(entity).add_child(child);
+        }
 
-/// The function that is used to interpolate between the noise values.
-///
-/// This function is used to make caves and land coexist. It's a smooth linear line from 0 to 256.
-/// TODO: Implement this into is_block in a way that makes sense.
-// fn noise_interpolation(y: i32) -> i32 {
-//     // Linear interpolation
-//     (y as f32 * 256.0 / CHUNK_HEIGHT as f32) as i32
-// }
+        for (position, color) in generated_chunk.lights {
+            let light = commands
+                .spawn(PointLightBundle {
+                    point_light: PointLight {
+                        color,
+                        intensity: BLOCK_LIGHT_INTENSITY,
+                        range: BLOCK_LIGHT_RANGE,
+                        ..default()
+                    },
+                    transform: Transform::from_translation(position),
+                    ..default()
+                })
+                .id();
+            commands.entity(entity).add_child(light);
+        }
+
+        // Check if there are vertices in the mesh.
+        let Some(collider) = generated_chunk.collider else {
+            // Keep the entity so the chunk still counts as loaded, but without anything to render.
+            commands
+                .entity(entity)
+                .remove::<(Handle<Mesh>, Aabb, Collider)>();
+
+            continue;
+        };
+
+        // Bevy only computes the bounds of entities that don't have any yet, so a re-meshed chunk would be culled with
+        // the bounds of its old mesh.
+        if let Some(aabb) = generated_chunk.mesh.compute_aabb() {
+            commands.entity(entity).insert(aabb);
+        }
 
-/// Remaps a value from one range to another.
-fn remap(value: f32, from_min: f32, from_max: f32, to_min: f32, to_max: f32) -> f32 {
-    (value - from_min) / (from_max - from_min) * (to_max - to_min) + to_min
+        // Greedy meshes tile the textures of a texture array, the others use the atlas.
+        let mesh = generated_chunk.mesh;
+        if per_block {
+            // Only the children are drawn.
+            commands
+                .entity(entity)
+                .remove::<(Handle<Mesh>, Handle<StandardMaterial>, Handle<ChunkArrayMaterial>)>()
+                .insert(Transform::from_translation(generated_chunk.translation));
+        } else if mesh.attribute(ATTRIBUTE_TEXTURE_LAYER).is_some() {
+            insert_chunk_mesh(
+                &mut commands,
+                entity,
+                &mut meshes,
+                mesh,
+                chunk_textures.material.clone(),
+                generated_chunk.translation,
+            );
+        } else {
+            insert_chunk_mesh(
+                &mut commands,
+                entity,
+                &mut meshes,
+                mesh,
+                chunk_materials.terrain.clone(),
+                generated_chunk.translation,
+            );
+        }
+        commands.entity(entity).insert(collider);
+    }
 }
 
 // Got this from bevy discord
@@ -689,14 +2271,14 @@ pub fn get_verts_indices(mesh: &Mesh) -> (Vec<Vec3>, Vec<[u32; 3]>) {
         },
     };
 
-    let indices = match mesh.indices().unwrap() {
-        Indices::U16(_) => {
-            panic!("expected u32 indices");
-        }
-        Indices::U32(indices) => indices
-            .chunks(3)
-            .map(|chunk| [chunk[0], chunk[1], chunk[2]])
-            .collect(),
-    };
+    let indices = mesh
+        .indices()
+        .unwrap()
+        .iter()
+        .map(|index| index as u32)
+        .collect::<Vec<_>>()
+        .chunks(3)
+        .map(|chunk| [chunk[0], chunk[1], chunk[2]])
+        .collect();
     (vertices, indices)
 }
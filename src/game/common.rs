@@ -1,17 +1,37 @@
-use bevy::{ecs::event::ManualEventReader, input::mouse::MouseMotion, prelude::*, tasks::Task};
-use std::{collections::HashSet, ops::Range};
+use bevy::{prelude::*, tasks::Task};
+use bevy_rapier3d::prelude::Collider;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, ops::Range, time::Duration};
+
+use super::coords::{chunk_local_to_world, world_to_chunk, world_to_local};
 
 pub const RENDER_DISTANCE: i32 = 17;
+/// How many chunks start generating per frame at most, so moving into a new area doesn't stall a frame.
+pub const CHUNK_TASKS_PER_FRAME: usize = 16;
+/// The size of the world in chunks, centered around the origin. `None` makes the world endless.
+pub const WORLD_CHUNKS: Option<IVec2XZ> = None; // Some(IVec2XZ { x: 16, z: 16 })
+/// How much of the render distance is clear of fog, see [`FogDistance`].
 pub const FOG_DISTANCE: f32 = 0.8;
+/// The color behind the sky and of the fog, so far away chunks fade into the sky.
+pub const SKY_COLOR: Color = Color::rgb(0.46, 0.6, 0.72);
 pub const SEED: u32 = 2137;
+/// Mixed into the seed of the cave noise, so it differs from the surface noise.
+pub const CAVE_SEED_SALT: u32 = 0x1234;
+/// Mixed into the seed of the biome noise.
+pub const BIOME_SEED_SALT: u32 = 0x5678;
+/// Mixed into the seed of the ridged noise of [`TerrainMode::Ridged3D`].
+pub const RIDGED_SEED_SALT: u32 = 0x9abc;
 
+/// The default width and depth of a chunk, see [`GenerationConfig::chunk_dims`].
 pub const CHUNK_SIZE: usize = 16;
+/// The default height of a chunk, which is also the height of the world.
 pub const CHUNK_HEIGHT: usize = 256;
 
 pub const SURFACE_SCALE: f64 = 0.004; //0.008
 // pub const BLEND_SCALE: f64 = 0.02; //0.02
 pub const CAVE_SCALE: f64 = 0.06; //0.06
 pub const ORE_SCALE: f64 = 0.1;
+pub const BIOME_SCALE: f64 = 0.0015;
 
 pub const DIAMOND_THRESHOLD: Range<f64> = 0.0..0.002;
 pub const REDSTONE_THRESHOLD: Range<f64> = 0.45..0.48;
@@ -20,25 +40,168 @@ pub const IRON_THRESHOLD: Range<f64> = 0.44..0.48;
 pub const COAL_THRESHOLD: Range<f64> = 0.58..0.7;
 
 pub const LAVA_HEIGHT: usize = 10;
+/// The default sea level, see [`GenerationConfig::sea_level`].
 pub const WATER_HEIGHT: usize = 69;
+/// How opaque the water material is.
+pub const WATER_ALPHA: f32 = 0.7;
 pub const CAVE_THRESHOLD: f64 = 0.32; //0.32
+/// How much the cave threshold changes with each press of + or -.
+pub const CAVE_THRESHOLD_STEP: f64 = 0.02;
+/// The fraction of solid blocks below the sea level the cave threshold suggested with T aims for.
+pub const TARGET_SOLID_FRACTION: f64 = 0.4;
+/// How many blocks are sampled to estimate the fraction of solid blocks.
+pub const SOLID_FRACTION_SAMPLES: u32 = 4096;
 // pub const CAVE_THRESHOLD_SURFACE: f64 = 0.2; //0.32 // TODO: Dokonczyc to
 pub const BLEND_HEIGHT: usize = 63; //85
 pub const FOV: f32 = 80.0;
+/// The antialiasing levels K cycles through, the game starts with the first one.
+pub const MSAA_CYCLE: [Msaa; 4] = [Msaa::Sample2, Msaa::Sample4, Msaa::Sample8, Msaa::Off];
+/// The width and height of the sun's shadow map, in texels.
+pub const SHADOW_MAP_SIZE: usize = 4096;
+/// How far from the camera the terrain still casts shadows.
+pub const SHADOW_DISTANCE: f32 = 200.0;
+
+/// The number of tiles in each row and column of the block texture atlas.
+pub const ATLAS_TILES: u32 = 7;
+/// The width and height of an atlas tile, in pixels.
+pub const ATLAS_TILE_SIZE: u32 = 32;
+/// How far the UVs are moved into each atlas tile (half a pixel of a tile).
+pub const ATLAS_TILE_INSET: f32 = 0.5 / ATLAS_TILE_SIZE as f32;
+/// How far the noise moves the surface of a [`TerrainMode::Sphere`] in or out, in blocks.
+pub const SPHERE_ROUGHNESS: f32 = 6.0;
+/// How far from the surface the noise of [`TerrainMode::Ridged3D`] can still fill or carve blocks, in blocks.
+pub const RIDGED_FALLOFF: f32 = 24.0;
+/// How many blocks of air the camera starts above the ground, see [`spawn_position`](super::terrain::spawn_position).
+pub const SPAWN_CLEARANCE: i32 = 3;
+/// How long the generation config has to stay unchanged before the chunks are regenerated, in seconds.
+pub const REGENERATE_DELAY: f32 = 0.3;
+/// How bright a face corner is for each ambient occlusion level, from fully occluded to not occluded at all.
+pub const AO_BRIGHTNESS: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
+/// The light level of a glowing block, see [`LightGrid`](super::light::LightGrid). It goes down by one per block.
+pub const MAX_LIGHT: u8 = 15;
+/// How much of its brightness a face loses for every light level below [`MAX_LIGHT`].
+pub const LIGHT_FALLOFF: f32 = 0.85;
+/// How bright the point light of a glowing block is, see [`BlockInfo::emissive`].
+pub const BLOCK_LIGHT_INTENSITY: f32 = 150.0;
+/// How far the point light of a glowing block reaches, in blocks.
+pub const BLOCK_LIGHT_RANGE: f32 = 10.0;
+/// How many blocks wide the noise overlay is, one pixel per block.
+pub const NOISE_OVERLAY_SIZE: u32 = 128;
+/// How far away blocks can be broken or placed, in blocks.
+pub const REACH: f32 = 8.0;
+/// The blocks that can be picked with the number keys 1 to 5.
+pub const HOTBAR: [BlockType; 5] = [
+    BlockType::Dirt,
+    BlockType::Stone,
+    BlockType::Grass,
+    BlockType::Log,
+    BlockType::Glowstone,
+];
+/// Where the world is saved with F5 and loaded from with F9.
+pub const SAVE_PATH: &str = "world.sav";
 
-// pub const SPEED: f32 = 10.0;
-// pub const GRAVITY: f32 = 9.81;
-// pub const JUMP_FORCE: f32 = 2.5; 
+/// How fast the fly camera moves.
+pub const FLY_SPEED: f32 = 24.0;
+/// How fast the player walks, in blocks per second.
+pub const WALK_SPEED: f32 = 5.0;
+pub const GRAVITY: f32 = 28.0;
+/// The upward velocity of a jump, enough to get on top of a block.
+pub const JUMP_SPEED: f32 = 9.0;
+pub const TERMINAL_VELOCITY: f32 = 50.0;
+/// Half the width of the player's box.
+pub const PLAYER_RADIUS: f32 = 0.3;
+/// How far the camera is above the bottom of the player's box.
+pub const PLAYER_EYE_HEIGHT: f32 = 1.6;
+/// How far the player's box reaches above the camera.
+pub const PLAYER_HEAD_HEIGHT: f32 = 0.2;
 
 // === COMPONENTS ===
 
+/// The movement of the camera while it's walking, see [`WalkMode`].
+#[derive(Component, Default)]
+pub struct PlayerPhysics {
+    pub velocity: Vec3,
+    /// Whether the player landed on a block in the last move, so it can jump.
+    pub on_ground: bool,
+}
+
 #[derive(Component)]
 pub struct ChunkMesh {
     pub position: IVec2XZ,
+    /// The level of detail of the mesh, meshed from every `lod`th block. 1 is full detail.
+    pub lod: usize,
 }
 
+/// The translucent water mesh of a chunk, spawned as a child of the [`ChunkMesh`] entity.
 #[derive(Component)]
-pub struct ComputeMeshTask(pub Task<Mesh>);
+pub struct ChunkWater;
+
+#[derive(Component)]
+pub struct ComputeMeshTask {
+    pub task: Task<GeneratedChunk>,
+    /// The chunk was already generated on the main thread, see [`GenerationConfig::synchronous`]. Only these tasks are
+    /// waited for, the ones started before the flag was turned on still finish in the background.
+    pub synchronous: bool,
+}
+
+/// The result of a [`ComputeMeshTask`].
+pub struct GeneratedChunk {
+    pub mesh: Mesh,
+    /// The faces of the translucent blocks, drawn with their own material. `None` if there are none.
+    pub water_mesh: Option<Mesh>,
+    /// The faces of each block type, drawn instead of `mesh` with the [`BlockMaterials`]. Only in
+    /// [`MeshingMode::PerBlock`].
+    pub block_meshes: Vec<(BlockType, Mesh)>,
+    /// `None` if the chunk has no faces.
+    pub collider: Option<Collider>,
+    /// The generated blocks. `None` if an existing chunk was only re-meshed, its [`ChunkData`] is already up to date.
+    pub blocks: Option<Vec<BlockType>>,
+    pub stats: ChunkStats,
+    /// Where the chunk entity goes. The origin of the chunk if the vertices are relative to it, otherwise zero.
+    pub translation: Vec3,
+    /// The centers of the glowing blocks relative to the chunk entity and their light, see [`BlockInfo::emissive`].
+    pub lights: Vec<(Vec3, Color)>,
+    /// The faces of `mesh`, if it can be patched after an edit, see [`ChunkFaces`].
+    pub faces: Option<Vec<(IVec3, BlockFace)>>,
+    /// How long the task took to generate and mesh the chunk, see [`GenTime`].
+    pub gen_time: Duration,
+}
+
+/// How long the last [`ComputeMeshTask`] of the chunk took from start to finish, to find the chunks that are slow to
+/// generate. Shows up on the chunk in the world inspector.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+#[reflect(Component)]
+pub struct GenTime(pub Duration);
+
+/// What a chunk is made of, filled in when it's meshed. Shows up on the chunk in the world inspector.
+#[derive(Component, Reflect, Default, Clone, Copy, Debug)]
+#[reflect(Component)]
+pub struct ChunkStats {
+    pub air: u32,
+    /// Blocks that can't be walked through, see [`BlockInfo::solid`].
+    pub solid: u32,
+    pub faces: u32,
+    pub vertices: u32,
+}
+
+/// The blocks of a chunk, indexed with [`block_index`]. Kept around so the chunk can be edited and re-meshed.
+#[derive(Component)]
+pub struct ChunkData(pub Vec<BlockType>);
+
+/// Marks a chunk whose [`ChunkData`] changed, so `remesh_dirty_chunks` re-meshes it once.
+#[derive(Component)]
+pub struct DirtyChunk;
+
+/// The block, relative to the chunk, and the face of every quad of a naive chunk mesh, in the order they are in the
+/// mesh. Lets `remesh_dirty_chunks` patch the faces around an edited block instead of re-meshing the whole chunk.
+/// Only on the chunks whose mesh can be patched.
+#[derive(Component)]
+pub struct ChunkFaces(pub Vec<(IVec3, BlockFace)>);
+
+/// The world positions of the blocks changed through the [`VoxelWorld`](super::world::VoxelWorld) since the last
+/// frame, and the block that was there before. Emptied by `remesh_dirty_chunks`.
+#[derive(Resource, Default)]
+pub struct BlockEdits(pub Vec<(IVec3, BlockType)>);
 
 #[derive(Component)]
 pub struct ChunkBorder;
@@ -48,21 +211,140 @@ pub struct UI;
 
 // === RESOURCES ===
 
-#[derive(Resource)]
+/// The chunks that are loaded (or generating), and their entities.
+#[derive(Resource, Default)]
 pub struct ChunksLoaded {
-    pub chunks: HashSet<IVec2XZ>,
+    pub chunks: HashMap<IVec2XZ, Entity>,
 }
 
-#[derive(Resource,Clone, Copy)]
-pub struct PlayerPos{
-    pub pos: Vec3,
-    pub rot: Quat,
+/// How far the chunk loading got, shown in the HUD.
+#[derive(Resource, Reflect, Default, Clone, Copy, Debug)]
+#[reflect(Resource)]
+pub struct ChunkProgress {
+    /// The chunks within the render distance, loaded or not.
+    pub total: u32,
+    /// The loaded chunks whose blocks are generated.
+    pub loaded: u32,
+    /// The generation and re-meshing tasks that are still running.
+    pub meshing: u32,
 }
 
-/// Keeps track of mouse motion events, pitch, and yaw
-#[derive(Resource, Default)]
-pub struct InputState {
-    pub reader_motion: ManualEventReader<MouseMotion>,
+/// The blocks the player changed, as `(block index, block)` overrides per chunk.
+///
+/// Chunks are generated from the noise again after they are unloaded, so the edits are applied on top of that.
+#[derive(Resource, Default, Clone)]
+pub struct ModifiedChunks {
+    pub chunks: HashMap<IVec2XZ, Vec<(usize, BlockType)>>,
+    /// The size of the chunks the block indices are for, see [`ModifiedChunks::resize`].
+    pub dims: ChunkDims,
+}
+
+impl ModifiedChunks {
+    /// Remembers that the block at the index of the chunk was changed.
+    pub fn record(&mut self, chunk_position: IVec2XZ, index: usize, block: BlockType) {
+        let overrides = self.chunks.entry(chunk_position).or_default();
+        match overrides.iter_mut().find(|(i, _)| *i == index) {
+            Some(entry) => entry.1 = block,
+            None => overrides.push((index, block)),
+        }
+    }
+
+    /// Applies the overrides of the chunk to its freshly generated blocks.
+    pub fn apply(&self, chunk_position: IVec2XZ, chunk_blocks: &mut [BlockType]) {
+        for &(index, block) in self.chunks.get(&chunk_position).into_iter().flatten() {
+            chunk_blocks[index] = block;
+        }
+    }
+
+    /// Returns the changed block at the world position, or `None` if it wasn't changed.
+    pub fn block(&self, pos: IVec3, dims: ChunkDims) -> Option<BlockType> {
+        let index = world_block_index(pos, dims)?;
+        self.chunks
+            .get(&IVec2XZ::from_block(pos, dims))?
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map(|&(_, block)| block)
+    }
+
+    /// Moves the edits into chunks of another size, if they were recorded for a different one. Every override keeps
+    /// its world position, only its chunk and block index change. Edits above the height of the new chunks are
+    /// dropped, those blocks don't exist anymore.
+    pub fn resize(&mut self, dims: ChunkDims) {
+        if self.dims == dims {
+            return;
+        }
+
+        let old_dims = self.dims;
+        let chunks = std::mem::take(&mut self.chunks);
+        self.dims = dims;
+        for (chunk_position, overrides) in chunks {
+            for (index, block) in overrides {
+                let (x, y, z) = block_position(index, old_dims);
+                let local = IVec3::new(x as i32, y as i32, z as i32);
+                let pos = chunk_local_to_world(chunk_position, local, old_dims);
+                if let Some(new_index) = world_block_index(pos, dims) {
+                    self.record(IVec2XZ::from_block(pos, dims), new_index, block);
+                }
+            }
+        }
+    }
+
+    /// Returns only the overrides of the chunk and the chunks next to it, which is all that generating it needs.
+    pub fn around(&self, chunk_position: IVec2XZ) -> ModifiedChunks {
+        let chunks = std::iter::once(chunk_position)
+            .chain(chunk_position.neighbors())
+            .filter_map(|position| Some((position, self.chunks.get(&position)?.clone())))
+            .collect();
+
+        ModifiedChunks {
+            chunks,
+            dims: self.dims,
+        }
+    }
+}
+
+/// How many chunks around the camera are loaded.
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct RenderDistance(pub i32);
+
+impl Default for RenderDistance {
+    fn default() -> Self {
+        Self(RENDER_DISTANCE)
+    }
+}
+
+/// Where the fog starts and where it hides everything, in blocks from the camera. Fading the chunks out before the
+/// render distance hides them being loaded and unloaded.
+#[derive(Resource, Reflect, Clone, Copy, Debug)]
+#[reflect(Resource)]
+pub struct FogDistance {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for FogDistance {
+    fn default() -> Self {
+        let clear_distance = RENDER_DISTANCE as f32 * FOG_DISTANCE * CHUNK_SIZE as f32;
+        Self {
+            start: clear_distance * 0.8,
+            end: clear_distance * 0.95,
+        }
+    }
+}
+
+/// The triangles drawn for all the loaded chunks, water included. Updated every second by `world_render_stats` and
+/// shows up in the world inspector.
+#[derive(Resource, Reflect, Default, Clone, Copy, Debug)]
+#[reflect(Resource)]
+pub struct WorldRenderStats {
+    /// The chunks that have a mesh, the ones still generating don't count.
+    pub chunks: u32,
+    pub triangles: u32,
+    /// The shortest, average and longest [`GenTime`] of the chunks.
+    pub min_gen_time: Duration,
+    pub avg_gen_time: Duration,
+    pub max_gen_time: Duration,
 }
 
 #[derive(Resource)]
@@ -71,12 +353,207 @@ pub struct ChunkBorderToggled(pub bool);
 #[derive(Resource)]
 pub struct Generating(pub bool);
 
+/// Whether a box is drawn around every loaded chunk.
+#[derive(Resource, Default)]
+pub struct ChunkGizmosToggled(pub bool);
+
+/// Whether the camera walks on the terrain instead of flying through it.
+#[derive(Resource, Default)]
+pub struct WalkMode(pub bool);
+
+/// Whether the chunks are drawn as wireframes.
+#[derive(Resource, Default)]
+pub struct WireframeToggled(pub bool);
+
+/// Whether the back faces of the chunks are drawn too.
+#[derive(Resource, Default)]
+pub struct DoubleSidedToggled(pub bool);
+
+/// The block placed with the right mouse button.
+#[derive(Resource)]
+pub struct SelectedBlock(pub BlockType);
+
+impl Default for SelectedBlock {
+    fn default() -> Self {
+        SelectedBlock(HOTBAR[0])
+    }
+}
+
 #[derive(Resource, Clone)]
 pub struct GameTextureAtlas(pub TextureAtlas);
 
+/// The light given off by every tile of the atlas, used as the emissive texture of the atlas material. Only the tiles
+/// of glowing blocks aren't black.
+#[derive(Resource)]
+pub struct EmissiveAtlas(pub Handle<Image>);
+
+/// The materials of the chunk meshes, shared by every chunk so there are only a few of them no matter how many chunks
+/// are loaded. The greedy meshes use the [`ChunkTextures`](super::texture_array::ChunkTextures) material instead.
+#[derive(Resource)]
+pub struct ChunkMaterials {
+    /// The solid blocks, textured from the atlas. The tiles of the glowing blocks glow through the [`EmissiveAtlas`].
+    pub terrain: Handle<StandardMaterial>,
+    /// The translucent blocks.
+    pub water: Handle<StandardMaterial>,
+}
+
+/// A material for every block type, used by the chunks meshed with [`MeshingMode::PerBlock`].
+#[derive(Resource, Default)]
+pub struct BlockMaterials(pub HashMap<BlockType, Handle<StandardMaterial>>);
+
+/// The parameters of the terrain generation. Can be tweaked from the inspector.
+#[derive(Resource, Reflect, Clone, Copy, Debug, Serialize, Deserialize)]
+#[reflect(Resource)]
+pub struct GenerationConfig {
+    pub seed: u32,
+    pub terrain_mode: TerrainMode,
+    pub surface_noise: SurfaceNoise,
+    /// Computes the normals from the triangles instead of using the ones of the faces, to check that they agree.
+    pub computed_normals: bool,
+    /// Averages the normals of the faces meeting at each corner, which makes the blocks look rounded. Off keeps the
+    /// flat normals of the faces.
+    pub smooth_normals: bool,
+    /// Bakes the vertices relative to their chunk instead of the world, which keeps them precise far from the origin.
+    pub local_space: bool,
+    /// Only generates the top block of each column, which is much faster to mesh for previewing big worlds.
+    pub preview: bool,
+    pub meshing: MeshingMode,
+    /// Merges the identical vertices of the naive meshes and logs how many are left. Slows down the meshing.
+    pub dedup_vertices: bool,
+    /// Greedy quads covering fewer blocks than this are dropped from the coarse meshes of the distant chunks, which
+    /// saves a lot of triangles on the horizon. The chunks at full detail are never simplified.
+    pub min_face_voxels: u32,
+    /// Bakes the light spreading from the glowing blocks into the vertex colors. Without `sky_light`, everything away
+    /// from them is dark.
+    pub block_light: bool,
+    /// Bakes the light falling straight down from the sky into the vertex colors, which darkens caves and overhangs.
+    /// Together with `block_light`, every block takes the brighter of both.
+    pub sky_light: bool,
+    /// Generates every chunk in range on the main thread as soon as it's needed, closest first, instead of a few per
+    /// frame on the async compute pool. Slow, but the chunks always show up in the same frame and order, so tests
+    /// that run the app should turn it on to get the same scene every time. Tests of a single chunk don't need it,
+    /// [`generate_chunk`](super::chunk::generate_chunk) never goes through the task pool.
+    pub synchronous: bool,
+    /// Where the camera starts, so the first chunks load around it. Handy to jump straight to a spot of a seed. The
+    /// camera is lifted above the ground of the column, the height is only used if the column has no ground at all.
+    pub spawn_center: IVec3,
+    /// The size of the chunks in blocks. Use [`GenerationConfig::chunk_dims`] to read it, that one is never zero.
+    pub chunk_dims: ChunkDims,
+    /// The scale of the 2D noise used for the surface height.
+    pub surface_scale: f64,
+    /// The number of noise layers making up the surface.
+    pub octaves: u32,
+    /// How much the frequency grows with each octave.
+    pub lacunarity: f64,
+    /// How much the amplitude shrinks with each octave.
+    pub persistence: f64,
+    /// How many blocks of dirt cover the stone.
+    pub surface_depth: u32,
+    /// How many layers of bedrock are at the bottom of the world. There's always at least one.
+    pub bedrock_layers: u32,
+    /// The air below this height is filled with water.
+    pub sea_level: u32,
+    /// The scale of the 2D noise picking the biomes. Smaller values make bigger biomes.
+    pub biome_scale: f64,
+    /// How far into each other the biomes blend, in biome noise values. 0 makes a cliff at the border.
+    pub biome_blend: f64,
+    /// The highest surface of the plains.
+    pub plains_height: f32,
+    /// The highest surface of the mountains.
+    pub mountains_height: f32,
+    /// The scale of the 3D noise used for the caves.
+    pub cave_scale: f64,
+    /// Caves are carved where the cave noise is above this value.
+    pub cave_threshold: f64,
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        Self {
+            seed: SEED,
+            terrain_mode: TerrainMode::default(),
+            surface_noise: SurfaceNoise::default(),
+            preview: false,
+            local_space: false,
+            computed_normals: false,
+            smooth_normals: false,
+            meshing: MeshingMode::default(),
+            dedup_vertices: false,
+            min_face_voxels: 0,
+            block_light: false,
+            sky_light: false,
+            synchronous: false,
+            spawn_center: IVec3::new(0, 100, 0),
+            chunk_dims: ChunkDims::default(),
+            surface_scale: SURFACE_SCALE,
+            octaves: 3,
+            lacunarity: 2.,
+            // All octaves have the same weight, like the ones of `SurfaceNoise::Classic`.
+            persistence: 1.,
+            surface_depth: 3,
+            bedrock_layers: 1,
+            sea_level: WATER_HEIGHT as u32,
+            biome_scale: BIOME_SCALE,
+            biome_blend: 0.15,
+            plains_height: 85.,
+            // 100 blocks below the height limit.
+            mountains_height: (CHUNK_HEIGHT - 100) as f32,
+            cave_scale: CAVE_SCALE,
+            cave_threshold: CAVE_THRESHOLD,
+        }
+    }
+}
+
+impl GenerationConfig {
+    /// The size of the chunks, at least 1 block along each axis.
+    pub fn chunk_dims(&self) -> ChunkDims {
+        ChunkDims::new(
+            self.chunk_dims.x.max(1),
+            self.chunk_dims.y.max(1),
+            self.chunk_dims.z.max(1),
+        )
+    }
+
+    /// The number of blocks in a chunk.
+    pub fn chunk_volume(&self) -> usize {
+        self.chunk_dims().volume()
+    }
+}
+
+/// The size of a chunk in blocks. Chunks are columns, so `y` is also the height of the world.
+#[derive(Reflect, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkDims {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+impl ChunkDims {
+    pub fn new(x: usize, y: usize, z: usize) -> Self {
+        ChunkDims { x, y, z }
+    }
+
+    /// The number of blocks in a chunk of this size.
+    pub fn volume(self) -> usize {
+        self.x * self.y * self.z
+    }
+}
+
+impl Default for ChunkDims {
+    fn default() -> Self {
+        ChunkDims::new(CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE)
+    }
+}
+
+impl std::fmt::Display for ChunkDims {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}x{}x{}", self.x, self.y, self.z)
+    }
+}
+
 // === ENUMS ===
 
-#[derive(PartialEq, Copy, Clone, Default)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Default, Serialize, Deserialize)]
 pub enum BlockType {
     Bedrock,
     Stone,
@@ -91,11 +568,205 @@ pub enum BlockType {
     IronOre,
     CoalOre,
     Sand,
+    Glowstone,
     #[default]
     Air,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+impl BlockType {
+    /// Every block type, in the order they are declared in.
+    pub const ALL: [BlockType; 15] = [
+        BlockType::Bedrock,
+        BlockType::Stone,
+        BlockType::Dirt,
+        BlockType::Grass,
+        BlockType::Log,
+        BlockType::Lava,
+        BlockType::Water,
+        BlockType::DiamondOre,
+        BlockType::RedstoneOre,
+        BlockType::GoldOre,
+        BlockType::IronOre,
+        BlockType::CoalOre,
+        BlockType::Sand,
+        BlockType::Glowstone,
+        BlockType::Air,
+    ];
+
+    /// Whether the block can be collided with. Air and liquids are there, but can be moved through.
+    pub fn is_solid(self) -> bool {
+        !matches!(self, BlockType::Air | BlockType::Water | BlockType::Lava)
+    }
+
+    /// How rough the surface of the block is. Water and lava are smooth enough to reflect the sky.
+    pub fn roughness(self) -> f32 {
+        match self {
+            BlockType::Water => 0.05,
+            BlockType::Lava => 0.3,
+            BlockType::IronOre | BlockType::GoldOre | BlockType::DiamondOre => 0.4,
+            _ => 0.9,
+        }
+    }
+
+    /// How metallic the block looks. Only the metal ores shine.
+    pub fn metallic(self) -> f32 {
+        match self {
+            BlockType::IronOre | BlockType::GoldOre => 0.6,
+            _ => 0.,
+        }
+    }
+
+    /// The light the block gives off. Black for the blocks that don't glow.
+    pub fn emissive(self) -> Color {
+        match self {
+            BlockType::Glowstone => Color::rgb(1.0, 0.8, 0.45),
+            _ => Color::BLACK,
+        }
+    }
+
+    /// Whether the player can break the block. The bedrock can't be, so nobody digs out of the bottom of the world.
+    pub fn is_breakable(self) -> bool {
+        self != BlockType::Bedrock
+    }
+
+    /// Whether the blocks behind this one can be seen, so the faces towards it have to be drawn.
+    pub fn is_transparent(self) -> bool {
+        matches!(self, BlockType::Air | BlockType::Water | BlockType::Lava)
+    }
+}
+
+/// How the terrain is generated.
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum TerrainMode {
+    /// The surface with 3D caves carved into it, which can make overhangs.
+    #[default]
+    Density3D,
+    /// Only the 2D surface height, every block below it is filled.
+    Heightmap,
+    /// Every block below `ground_height` is filled, without any noise. For testing against a predictable surface.
+    Flat { ground_height: u32 },
+    /// A ball of blocks around `center`, with its surface pushed in and out by up to [`SPHERE_ROUGHNESS`] blocks.
+    Sphere { center: IVec3, radius: f32 },
+    /// The surface pushed in and out by 3D ridged noise, up to [`RIDGED_FALLOFF`] blocks away from it. The ridges
+    /// leave arches and overhangs a heightmap can't make. `frequency` scales the noise per block.
+    Ridged3D { frequency: f64, octaves: u32 },
+}
+
+/// The noise function that shapes the surface height. All of them use the octaves of the [`GenerationConfig`].
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum SurfaceNoise {
+    /// The octaves the terrain was always made of: Perlin noise at 1, 2, 3... times the base frequency, all with the
+    /// same weight. Ignores the lacunarity and persistence, so the default world stays the same.
+    #[default]
+    Classic,
+    /// Octaves of Perlin noise summed by `fbm`.
+    Perlin,
+    /// The fractal Brownian motion of the noise crate.
+    Fbm,
+    /// Ridged multifractal noise, which makes sharp mountain ridges.
+    Ridged,
+}
+
+/// The kind of land in a column of the world, see `biome_at`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Biome {
+    /// Low and gentle hills covered in grass.
+    Plains,
+    /// High and rocky.
+    Mountains,
+}
+
+impl Biome {
+    /// The block at the top of the surface.
+    pub fn surface_block(self) -> BlockType {
+        match self {
+            Biome::Plains => BlockType::Grass,
+            Biome::Mountains => BlockType::Stone,
+        }
+    }
+
+    /// The blocks between the surface block and the stone.
+    pub fn filler_block(self) -> BlockType {
+        match self {
+            Biome::Plains => BlockType::Dirt,
+            Biome::Mountains => BlockType::Stone,
+        }
+    }
+}
+
+/// How the chunk meshes are built.
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum MeshingMode {
+    /// One quad per visible block face.
+    #[default]
+    Naive,
+    /// Neighbouring faces of the same block are merged into bigger quads. The UVs are counted in blocks so the
+    /// texture repeats, which is why these are drawn from a texture array.
+    Greedy,
+    /// Like `Naive`, but with a mesh for every block type, so each block type gets its own material.
+    PerBlock,
+    /// Like `Naive`, but every chunk is built from every n-th block no matter how far away it is, to compare the
+    /// levels of detail up close. 1 is full detail.
+    Lod(u32),
+}
+
+impl MeshingMode {
+    /// The modes `M` cycles through.
+    pub const CYCLE: [MeshingMode; 5] = [
+        MeshingMode::Naive,
+        MeshingMode::Greedy,
+        MeshingMode::PerBlock,
+        MeshingMode::Lod(2),
+        MeshingMode::Lod(4),
+    ];
+
+    /// The level of detail of a chunk, given the one for its distance from the camera.
+    pub fn lod(self, distance_lod: usize) -> usize {
+        match self {
+            MeshingMode::Lod(lod) => (lod as usize).max(1),
+            _ => distance_lod,
+        }
+    }
+
+    /// The mode after this one in [`MeshingMode::CYCLE`].
+    pub fn next(self) -> Self {
+        let index = Self::CYCLE.iter().position(|&mode| mode == self);
+        Self::CYCLE[index.map_or(0, |index| (index + 1) % Self::CYCLE.len())]
+    }
+}
+
+/// Which axis points up in the meshes that leave the game, like the exported OBJ files. The game itself is always
+/// Y-up like Bevy, so the chunks are generated and meshed Y-up and turned afterwards.
+#[derive(Reflect, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    /// For the tools that use Z-up, like Blender. The columns of the heightmap run along Z and the ground is the XY
+    /// plane.
+    Z,
+}
+
+impl UpAxis {
+    /// Turns a Y-up position or normal into this convention. Z-up is turned about the X axis, so the forward -Z of
+    /// Bevy becomes +Y and the mesh isn't mirrored.
+    pub fn from_y_up(self, v: Vec3) -> Vec3 {
+        match self {
+            UpAxis::Y => v,
+            UpAxis::Z => Vec3::new(v.x, -v.z, v.y),
+        }
+    }
+}
+
+/// How the chunks are exported with F6. Not part of the [`GenerationConfig`], so changing it doesn't regenerate the
+/// world.
+#[derive(Resource, Reflect, Default, Clone, Copy, Debug)]
+#[reflect(Resource)]
+pub struct ExportSettings {
+    /// The up axis of the exported chunks, see [`UpAxis`].
+    pub up_axis: UpAxis,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum BlockFace {
     Top,
     Bottom,
@@ -105,9 +776,263 @@ pub enum BlockFace {
     Back,
 }
 
+impl BlockFace {
+    /// Every face, in the order they are declared in.
+    pub const ALL: [BlockFace; 6] = [
+        BlockFace::Top,
+        BlockFace::Bottom,
+        BlockFace::Left,
+        BlockFace::Right,
+        BlockFace::Front,
+        BlockFace::Back,
+    ];
+
+    /// The position of the neighbouring block this face is touching, relative to the block.
+    pub fn offset(self) -> IVec3 {
+        match self {
+            BlockFace::Top => IVec3::new(0, 1, 0),
+            BlockFace::Bottom => IVec3::new(0, -1, 0),
+            BlockFace::Left => IVec3::new(-1, 0, 0),
+            BlockFace::Right => IVec3::new(1, 0, 0),
+            BlockFace::Front => IVec3::new(0, 0, 1),
+            BlockFace::Back => IVec3::new(0, 0, -1),
+        }
+    }
+
+    /// The outward normal of the face.
+    pub fn normal(self) -> [f32; 3] {
+        match self {
+            BlockFace::Top => [0.0, 1.0, 0.0],
+            BlockFace::Bottom => [0.0, -1.0, 0.0],
+            BlockFace::Left => [-1.0, 0.0, 0.0],
+            BlockFace::Right => [1.0, 0.0, 0.0],
+            BlockFace::Front => [0.0, 0.0, 1.0],
+            BlockFace::Back => [0.0, 0.0, -1.0],
+        }
+    }
+
+    /// The axis the face points along, 0 for x, 1 for y and 2 for z.
+    pub fn axis(self) -> usize {
+        match self {
+            BlockFace::Left | BlockFace::Right => 0,
+            BlockFace::Top | BlockFace::Bottom => 1,
+            BlockFace::Front | BlockFace::Back => 2,
+        }
+    }
+}
+
+/// A set of [`BlockFace`]s, one bit per face. Used for the faces of a block that have to be drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FaceMask(pub u8);
+
+impl FaceMask {
+    pub fn set_face(&mut self, face: BlockFace) {
+        self.0 |= 1 << face as u8;
+    }
+
+    pub fn has_face(self, face: BlockFace) -> bool {
+        self.0 & (1 << face as u8) != 0
+    }
+
+    /// Iterates over the faces in the set, in the order they are declared in.
+    pub fn exposed_faces(self) -> impl Iterator<Item = BlockFace> {
+        BlockFace::ALL
+            .into_iter()
+            .filter(move |&face| self.has_face(face))
+    }
+}
+
+// === BLOCK REGISTRY ===
+
+/// The atlas tiles used by the faces of a block.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BlockTextures {
+    pub top: u32,
+    pub side: u32,
+    pub bottom: u32,
+}
+
+impl BlockTextures {
+    /// Uses the same tile on every face.
+    pub fn all(tile: u32) -> Self {
+        Self {
+            top: tile,
+            side: tile,
+            bottom: tile,
+        }
+    }
+
+    /// Returns the tile of the face.
+    pub fn face(self, face: BlockFace) -> u32 {
+        match face {
+            BlockFace::Top => self.top,
+            BlockFace::Bottom => self.bottom,
+            _ => self.side,
+        }
+    }
+}
+
+/// The properties of a block type.
+#[derive(Clone, Copy, Debug)]
+pub struct BlockInfo {
+    /// Whether the block can be collided with.
+    pub solid: bool,
+    /// Whether the blocks behind this one can be seen, so their faces have to be drawn.
+    pub transparent: bool,
+    /// Whether the block itself is see-through, like water. Its faces go into the water mesh.
+    pub translucent: bool,
+    pub textures: BlockTextures,
+    /// How rough the block looks with its own material, see [`BlockMaterials`].
+    pub roughness: f32,
+    pub metallic: f32,
+    /// The light the block gives off. Its faces glow with it and every block gets a dim point light.
+    pub emissive: Color,
+}
+
+impl Default for BlockInfo {
+    /// Behaves like air.
+    fn default() -> Self {
+        Self {
+            solid: false,
+            transparent: true,
+            translucent: false,
+            textures: BlockTextures::default(),
+            roughness: 1.,
+            metallic: 0.,
+            emissive: Color::BLACK,
+        }
+    }
+}
+
+/// Maps every [`BlockType`] to its [`BlockInfo`], so adding a block doesn't need changes in the mesher.
+#[derive(Resource, Clone, Debug)]
+pub struct BlockRegistry {
+    blocks: Vec<BlockInfo>,
+}
+
+impl BlockRegistry {
+    /// Returns the properties of the block. Unregistered blocks behave like air.
+    pub fn get(&self, block: BlockType) -> BlockInfo {
+        self.blocks
+            .get(block as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets the properties of the block.
+    pub fn register(&mut self, block: BlockType, info: BlockInfo) {
+        let index = block as usize;
+        if self.blocks.len() <= index {
+            self.blocks.resize(index + 1, BlockInfo::default());
+        }
+        self.blocks[index] = info;
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        let mut registry = Self { blocks: Vec::new() };
+
+        // The properties come from the block type, only the textures are set here.
+        let mut register = |block: BlockType, textures| {
+            registry.register(
+                block,
+                BlockInfo {
+                    solid: block.is_solid(),
+                    transparent: block.is_transparent(),
+                    translucent: block == BlockType::Water,
+                    textures,
+                    roughness: block.roughness(),
+                    metallic: block.metallic(),
+                    emissive: block.emissive(),
+                },
+            )
+        };
+
+        // The tiles are the indices in blocks.png, row by row.
+        register(BlockType::Air, BlockTextures::default());
+        register(BlockType::Bedrock, BlockTextures::all(0));
+        register(BlockType::Stone, BlockTextures::all(1));
+        register(BlockType::Dirt, BlockTextures::all(2));
+        register(
+            BlockType::Grass,
+            BlockTextures {
+                top: 3,
+                side: 4,
+                bottom: 2,
+            },
+        );
+        register(
+            BlockType::Log,
+            BlockTextures {
+                top: 12,
+                side: 5,
+                bottom: 12,
+            },
+        );
+        register(BlockType::Lava, BlockTextures::all(21));
+        register(BlockType::Water, BlockTextures::all(22));
+        register(BlockType::DiamondOre, BlockTextures::all(15));
+        register(BlockType::RedstoneOre, BlockTextures::all(14));
+        register(BlockType::GoldOre, BlockTextures::all(9));
+        register(BlockType::IronOre, BlockTextures::all(8));
+        register(BlockType::CoalOre, BlockTextures::all(7));
+        register(BlockType::Sand, BlockTextures::all(10));
+        register(BlockType::Glowstone, BlockTextures::all(16));
+
+        registry
+    }
+}
+
+// === CHUNK DATA ===
+
+/// Returns the index of the block at the local position in the flat data of a chunk of the given size.
+pub fn block_index(x: usize, y: usize, z: usize, dims: ChunkDims) -> usize {
+    x + y * dims.x + z * dims.x * dims.y
+}
+
+/// Returns the index of the block at the local position, or `None` if the position is outside of the chunk.
+pub fn local_block_index(pos: IVec3, dims: ChunkDims) -> Option<usize> {
+    let inside = (0..dims.x as i32).contains(&pos.x)
+        && (0..dims.y as i32).contains(&pos.y)
+        && (0..dims.z as i32).contains(&pos.z);
+    inside.then(|| block_index(pos.x as usize, pos.y as usize, pos.z as usize, dims))
+}
+
+/// Returns the block at the local position in the flat data of a chunk, or `None` if the position is outside of the
+/// chunk. Saves checking the bounds by hand, which is easy to get off by one.
+pub fn try_block(chunk_blocks: &[BlockType], pos: IVec3, dims: ChunkDims) -> Option<BlockType> {
+    chunk_blocks.get(local_block_index(pos, dims)?).copied()
+}
+
+/// Returns the index of the block at the world position in the data of the chunk containing it, or `None` if it's
+/// above or below the world.
+pub fn world_block_index(pos: IVec3, dims: ChunkDims) -> Option<usize> {
+    if !(0..dims.y as i32).contains(&pos.y) {
+        return None;
+    }
+
+    let local = world_to_local(pos, dims);
+    Some(block_index(
+        local.x as usize,
+        local.y as usize,
+        local.z as usize,
+        dims,
+    ))
+}
+
+/// Returns the local position of the block at the index in the flat chunk data. The opposite of [`block_index`].
+pub fn block_position(index: usize, dims: ChunkDims) -> (usize, usize, usize) {
+    (
+        index % dims.x,
+        index / dims.x % dims.y,
+        index / (dims.x * dims.y),
+    )
+}
+
 // === IVEC2XZ ===
 
-#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IVec2XZ {
     pub x: i32,
     pub z: i32,
@@ -117,6 +1042,42 @@ impl IVec2XZ {
     pub fn new(x: i32, z: i32) -> Self {
         IVec2XZ { x, z }
     }
+
+    /// Returns the position of the chunk containing the world block position, see [`world_to_chunk`].
+    pub fn from_block(pos: IVec3, dims: ChunkDims) -> Self {
+        world_to_chunk(pos, dims)
+    }
+
+    /// Returns the position of the chunk containing the world position, like the camera.
+    pub fn from_world(pos: Vec3, dims: ChunkDims) -> Self {
+        IVec2XZ::from_block(pos.floor().as_ivec3(), dims)
+    }
+
+    /// Returns the world position of the block in the corner of the chunk, at the bottom of the world.
+    pub fn origin(self, dims: ChunkDims) -> IVec3 {
+        IVec3::new(self.x * dims.x as i32, 0, self.z * dims.z as i32)
+    }
+
+    /// Returns the positions of the four chunks sharing a border with this one.
+    pub fn neighbors(self) -> [IVec2XZ; 4] {
+        [
+            self + IVec2XZ::new(1, 0),
+            self + IVec2XZ::new(-1, 0),
+            self + IVec2XZ::new(0, 1),
+            self + IVec2XZ::new(0, -1),
+        ]
+    }
+
+    /// Checks if this chunk position is part of the world grid (see [`WORLD_CHUNKS`]).
+    pub fn in_world(self) -> bool {
+        match WORLD_CHUNKS {
+            Some(size) => {
+                (-size.x / 2..size.x - size.x / 2).contains(&self.x)
+                    && (-size.z / 2..size.z - size.z / 2).contains(&self.z)
+            }
+            None => true,
+        }
+    }
 }
 
 impl std::ops::Add for IVec2XZ {
@@ -0,0 +1,34 @@
+//! Conversions between the three kinds of block positions:
+//!
+//! - world positions, the same everywhere,
+//! - chunk positions, see [`IVec2XZ`]. Chunks are columns, so they only have an X and a Z,
+//! - local positions inside a chunk, from 0 to the [`ChunkDims`] on each axis.
+//!
+//! Negative world positions round down, so world X -1 is in chunk -1 at local X `dims.x - 1`, not in chunk 0.
+
+use bevy::prelude::*;
+
+use super::common::*;
+
+/// Returns the position of the chunk containing the world block position.
+pub fn world_to_chunk(pos: IVec3, dims: ChunkDims) -> IVec2XZ {
+    IVec2XZ::new(
+        pos.x.div_euclid(dims.x as i32),
+        pos.z.div_euclid(dims.z as i32),
+    )
+}
+
+/// Returns the position of the world block inside its chunk. The height stays the same, chunks span the whole world.
+pub fn world_to_local(pos: IVec3, dims: ChunkDims) -> IVec3 {
+    IVec3::new(
+        pos.x.rem_euclid(dims.x as i32),
+        pos.y,
+        pos.z.rem_euclid(dims.z as i32),
+    )
+}
+
+/// Returns the world position of the block at the local position in the chunk. The opposite of [`world_to_chunk`]
+/// and [`world_to_local`].
+pub fn chunk_local_to_world(chunk: IVec2XZ, local: IVec3, dims: ChunkDims) -> IVec3 {
+    chunk.origin(dims) + local
+}
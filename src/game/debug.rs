@@ -1,10 +1,15 @@
-use std::collections::HashSet;
-
+use bevy::ecs::query::Has;
+use bevy::pbr::wireframe::Wireframe;
 use bevy::prelude::*;
+use bevy::render::primitives::{Aabb, Frustum};
+use bevy::render::render_resource::Face;
 use bevy::window::PresentMode;
 use bevy_prototype_debug_lines::DebugLines;
+use std::time::Duration;
 
 use super::common::*;
+use super::terrain::{estimate_solid_fraction, suggest_cave_threshold};
+use super::texture_array::{ChunkArrayMaterial, ChunkTextures};
 
 pub fn debug_keyboard(
     keyboard_input: Res<Input<KeyCode>>,
@@ -24,7 +29,7 @@ pub fn debug_keyboard(
         for entity in chunk_query.iter() {
             commands.entity(entity).despawn_recursive();
         }
-        chunks_loaded.chunks = HashSet::new();
+        chunks_loaded.chunks.clear();
     }
     if keyboard_input.just_pressed(KeyCode::G) {
         // Toggle the chunk border.
@@ -43,10 +48,241 @@ pub fn debug_keyboard(
     }
 }
 
+/// Nudges the [`GenerationConfig::cave_threshold`] with + and -, which regenerates the chunks like a change in the
+/// inspector does.
+pub fn cave_threshold_keyboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut generation_config: ResMut<GenerationConfig>,
+) {
+    let step = if keyboard_input.any_just_pressed([KeyCode::Equals, KeyCode::NumpadAdd]) {
+        CAVE_THRESHOLD_STEP
+    } else if keyboard_input.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        -CAVE_THRESHOLD_STEP
+    } else {
+        return;
+    };
+
+    // The noise never leaves (-1, 1), so past that there are no caves or only caves.
+    generation_config.cave_threshold = (generation_config.cave_threshold + step).clamp(-1., 1.);
+    info!("Cave threshold: {:.2}", generation_config.cave_threshold);
+}
+
+/// Switches to the next [`MeshingMode`] with M, which re-meshes the chunks like a change in the inspector does.
+pub fn meshing_keyboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut generation_config: ResMut<GenerationConfig>,
+) {
+    if keyboard_input.just_pressed(KeyCode::M) {
+        generation_config.meshing = generation_config.meshing.next();
+        info!("Meshing mode: {:?}", generation_config.meshing);
+    }
+}
+
+/// Logs which fraction of the blocks below the sea level is solid with T, and the cave threshold that would make it
+/// [`TARGET_SOLID_FRACTION`]. Takes a moment, the blocks are generated like in the chunks.
+pub fn solid_fraction_keyboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    generation_config: Res<GenerationConfig>,
+) {
+    if keyboard_input.just_pressed(KeyCode::T) {
+        let fraction = estimate_solid_fraction(&generation_config, SOLID_FRACTION_SAMPLES);
+        let threshold = suggest_cave_threshold(
+            &generation_config,
+            TARGET_SOLID_FRACTION,
+            SOLID_FRACTION_SAMPLES,
+        );
+        info!(
+            "{:.0}% of the blocks are solid, a cave threshold of {:.2} would make it {:.0}%",
+            fraction * 100.,
+            threshold,
+            TARGET_SOLID_FRACTION * 100.
+        );
+    }
+}
+
+/// Switches to the next antialiasing level of [`MSAA_CYCLE`] with K. More samples smooth the block edges, but cost a
+/// lot on a dense scene.
+pub fn msaa_keyboard(keyboard_input: Res<Input<KeyCode>>, mut msaa: ResMut<Msaa>) {
+    if keyboard_input.just_pressed(KeyCode::K) {
+        let index = MSAA_CYCLE.iter().position(|&level| level == *msaa);
+        *msaa = MSAA_CYCLE[index.map_or(0, |index| (index + 1) % MSAA_CYCLE.len())];
+        info!("MSAA: {:?}", *msaa);
+    }
+}
+
+/// Moves on to the next seed with N, which regenerates the chunks like a change in the inspector does.
+pub fn seed_keyboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut generation_config: ResMut<GenerationConfig>,
+) {
+    if keyboard_input.just_pressed(KeyCode::N) {
+        generation_config.seed = generation_config.seed.wrapping_add(1);
+        info!("Seed: {}", generation_config.seed);
+    }
+}
+
+/// Draws the back faces of the chunks too with C. A face that shows up then is wound the wrong way, one that is still
+/// missing was never meshed.
+pub fn chunk_double_sided(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut double_sided_toggled: ResMut<DoubleSidedToggled>,
+    chunk_materials: Res<ChunkMaterials>,
+    block_materials: Res<BlockMaterials>,
+    chunk_textures: Res<ChunkTextures>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut array_materials: ResMut<Assets<ChunkArrayMaterial>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::C) {
+        return;
+    }
+    double_sided_toggled.0 = !double_sided_toggled.0;
+    let double_sided = double_sided_toggled.0;
+
+    let handles = [&chunk_materials.terrain, &chunk_materials.water]
+        .into_iter()
+        .chain(block_materials.0.values());
+    for handle in handles {
+        if let Some(material) = materials.get_mut(handle) {
+            material.cull_mode = (!double_sided).then_some(Face::Back);
+            // Lights the back faces like front faces instead of leaving them dark.
+            material.double_sided = double_sided;
+        }
+    }
+    if let Some(material) = array_materials.get_mut(&chunk_textures.material) {
+        material.double_sided = double_sided;
+    }
+
+    info!("Back faces: {}", if double_sided { "drawn" } else { "culled" });
+}
+
+/// Toggles drawing the chunks as wireframes with F3, to see how the faces are merged.
+pub fn chunk_wireframe(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands,
+    mut wireframe_toggled: ResMut<WireframeToggled>,
+    chunk_query: Query<(Entity, Has<Wireframe>), With<ChunkMesh>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        wireframe_toggled.0 = !wireframe_toggled.0;
+    }
+
+    // Also catches the chunks that were spawned since the last toggle.
+    for (entity, has_wireframe) in chunk_query.iter() {
+        if wireframe_toggled.0 && !has_wireframe {
+            commands.entity(entity).insert(Wireframe);
+        } else if !wireframe_toggled.0 && has_wireframe {
+            commands.entity(entity).remove::<Wireframe>();
+        }
+    }
+}
+
+/// Toggles a box around every loaded chunk with B. Generated chunks are green, the ones still generating are yellow.
+pub fn chunk_gizmos(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut gizmos: Gizmos,
+    mut chunk_gizmos_toggled: ResMut<ChunkGizmosToggled>,
+    chunk_query: Query<(&ChunkMesh, Has<ChunkData>)>,
+    generation_config: Res<GenerationConfig>,
+) {
+    if keyboard_input.just_pressed(KeyCode::B) {
+        chunk_gizmos_toggled.0 = !chunk_gizmos_toggled.0;
+    }
+    if !chunk_gizmos_toggled.0 {
+        return;
+    }
+
+    let dims = generation_config.chunk_dims();
+    let size = Vec3::new(dims.x as f32, dims.y as f32, dims.z as f32);
+    for (chunk_mesh, generated) in chunk_query.iter() {
+        let corner = chunk_mesh.position.origin(dims).as_vec3();
+        let color = if generated { Color::GREEN } else { Color::YELLOW };
+
+        gizmos.cuboid(
+            Transform::from_translation(corner + size / 2.).with_scale(size),
+            color,
+        );
+    }
+}
+
+/// Logs how many of the loaded chunks are inside the camera frustum, to check the culling and tune the render
+/// distance. Chunks that are still generating have no mesh yet, so they only count as loaded.
+pub fn chunk_visibility(
+    camera_query: Query<&Frustum, With<Camera3d>>,
+    chunk_query: Query<(Option<&Aabb>, &GlobalTransform), With<ChunkMesh>>,
+) {
+    let Ok(frustum) = camera_query.get_single() else {
+        return;
+    };
+
+    let loaded = chunk_query.iter().count();
+    let visible = chunk_query
+        .iter()
+        .filter_map(|(aabb, transform)| Some((aabb?, transform)))
+        .filter(|(aabb, transform)| frustum.intersects_obb(aabb, &transform.affine(), true, true))
+        .count();
+
+    info!("Chunks in view: {} of {} loaded", visible, loaded);
+}
+
+/// Counts the triangles of all the loaded chunk meshes into [`WorldRenderStats`] and logs them, to see what greedy
+/// meshing and the render distance cost. Also sums up how long the chunks took to generate, see [`GenTime`].
+pub fn world_render_stats(
+    mut stats: ResMut<WorldRenderStats>,
+    meshes: Res<Assets<Mesh>>,
+    chunk_query: Query<
+        (Option<&Handle<Mesh>>, Option<&Children>, Option<&GenTime>),
+        With<ChunkMesh>,
+    >,
+    mesh_query: Query<&Handle<Mesh>>,
+) {
+    let triangles = |handle: &Handle<Mesh>| {
+        meshes.get(handle).map_or(0, |mesh| {
+            mesh.indices().map_or(mesh.count_vertices(), |indices| indices.len()) as u32 / 3
+        })
+    };
+
+    *stats = WorldRenderStats::default();
+    let mut timed_chunks = 0;
+    let mut total_gen_time = Duration::ZERO;
+    for (mesh, children, gen_time) in chunk_query.iter() {
+        // The water and the per block meshes are children of the chunk.
+        let child_meshes = children
+            .into_iter()
+            .flat_map(|children| children.iter())
+            .filter_map(|&child| mesh_query.get(child).ok());
+        let chunk_triangles: u32 = mesh.into_iter().chain(child_meshes).map(triangles).sum();
+
+        if chunk_triangles > 0 {
+            stats.chunks += 1;
+            stats.triangles += chunk_triangles;
+        }
+
+        if let Some(&GenTime(gen_time)) = gen_time {
+            stats.min_gen_time = if timed_chunks == 0 {
+                gen_time
+            } else {
+                stats.min_gen_time.min(gen_time)
+            };
+            stats.max_gen_time = stats.max_gen_time.max(gen_time);
+            total_gen_time += gen_time;
+            timed_chunks += 1;
+        }
+    }
+    if timed_chunks > 0 {
+        stats.avg_gen_time = total_gen_time / timed_chunks;
+    }
+
+    info!(
+        "Triangles: {} in {} chunks, generated in {:?} min, {:?} avg, {:?} max",
+        stats.triangles, stats.chunks, stats.min_gen_time, stats.avg_gen_time, stats.max_gen_time
+    );
+}
+
 pub fn chunk_border(
     mut lines: ResMut<DebugLines>,
     camera: Query<&Transform, With<Camera>>,
     chunk_border_toggled: Res<ChunkBorderToggled>,
+    generation_config: Res<GenerationConfig>,
 ) {
     // Check if the chunk border should be drawn.
     if chunk_border_toggled.0 {
@@ -55,20 +291,18 @@ pub fn chunk_border(
 
     // Draw a "box" around the selected chunk.
     // Determine the current from the camera position
+    let dims = generation_config.chunk_dims();
     let camera_position = camera.single().translation;
-    let current_chunk: IVec2XZ = IVec2XZ::new(
-        (camera_position.x / CHUNK_SIZE as f32).floor() as i32,
-        (camera_position.z / CHUNK_SIZE as f32).floor() as i32,
-    );
+    let current_chunk: IVec2XZ = IVec2XZ::from_world(camera_position, dims);
 
     // Draw the lines around the current chunk.
-    let x1 = current_chunk.x * CHUNK_SIZE as i32;
+    let x1 = current_chunk.x * dims.x as i32;
     let y1 = 0;
-    let z1 = current_chunk.z * CHUNK_SIZE as i32;
+    let z1 = current_chunk.z * dims.z as i32;
 
-    let x2 = x1 + CHUNK_SIZE as i32;
-    let y2 = CHUNK_HEIGHT as i32;
-    let z2 = z1 + CHUNK_SIZE as i32;
+    let x2 = x1 + dims.x as i32;
+    let y2 = dims.y as i32;
+    let z2 = z1 + dims.z as i32;
 
     let duration = 0.0;
     let color = Color::rgb(1.0, 0.0, 0.0);
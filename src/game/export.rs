@@ -0,0 +1,125 @@
+use bevy::{prelude::*, render::mesh::VertexAttributeValues, window::PrimaryWindow};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use super::common::*;
+use super::interaction::cursor_grabbed;
+use super::raycast::raycast_voxel;
+use super::world::VoxelWorld;
+
+/// Writes the positions, normals, UVs and triangles of the mesh to a Wavefront OBJ file, e.g. to look at it in
+/// Blender.
+///
+/// Normals and UVs are left out if the mesh doesn't have them. Meshes without indices are read as a plain triangle
+/// list.
+pub fn export_mesh_obj(mesh: &Mesh, path: &Path) -> io::Result<()> {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the mesh has no positions"));
+    };
+    let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+        Some(VertexAttributeValues::Float32x3(normals)) => Some(normals),
+        _ => None,
+    };
+    let uvs = match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+        Some(VertexAttributeValues::Float32x2(uvs)) => Some(uvs),
+        _ => None,
+    };
+
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    for [x, y, z] in positions {
+        writeln!(writer, "v {} {} {}", x, y, z)?;
+    }
+    for [u, v] in uvs.into_iter().flatten() {
+        // OBJ puts the origin of the texture at the bottom, Bevy at the top.
+        writeln!(writer, "vt {} {}", u, 1. - v)?;
+    }
+    for [x, y, z] in normals.into_iter().flatten() {
+        writeln!(writer, "vn {} {} {}", x, y, z)?;
+    }
+
+    // Works the same for 16 and 32 bit indices.
+    let indices: Vec<usize> = match mesh.indices() {
+        Some(indices) => indices.iter().collect(),
+        None => (0..positions.len()).collect(),
+    };
+
+    for triangle in indices.chunks_exact(3) {
+        write!(writer, "f")?;
+        for &index in triangle {
+            // OBJ indices start at 1.
+            let index = index + 1;
+            match (uvs.is_some(), normals.is_some()) {
+                (true, true) => write!(writer, " {}/{}/{}", index, index, index)?,
+                (true, false) => write!(writer, " {}/{}", index, index)?,
+                (false, true) => write!(writer, " {}//{}", index, index)?,
+                (false, false) => write!(writer, " {}", index)?,
+            }
+        }
+        writeln!(writer)?;
+    }
+
+    writer.flush()
+}
+
+/// Turns the positions and normals of a Y-up mesh so `up_axis` points up, see [`UpAxis::from_y_up`].
+pub fn convert_up_axis(mesh: &mut Mesh, up_axis: UpAxis) {
+    for attribute in [Mesh::ATTRIBUTE_POSITION, Mesh::ATTRIBUTE_NORMAL] {
+        if let Some(VertexAttributeValues::Float32x3(values)) = mesh.attribute_mut(attribute) {
+            for value in values {
+                *value = up_axis.from_y_up(Vec3::from_array(*value)).to_array();
+            }
+        }
+    }
+}
+
+/// Exports the mesh of the chunk the camera is looking at to `chunk_<x>_<z>.obj` with F6. Only the solid blocks are
+/// exported, not the water. The mesh is turned to the up axis of the [`ExportSettings`] first.
+pub fn export_keyboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<&Transform, With<Camera3d>>,
+    world: VoxelWorld,
+    mesh_query: Query<&Handle<Mesh>, With<ChunkMesh>>,
+    meshes: Res<Assets<Mesh>>,
+    block_registry: Res<BlockRegistry>,
+    export_settings: Res<ExportSettings>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F6) || !cursor_grabbed(&windows) {
+        return;
+    }
+
+    let camera = camera_query.single();
+    let Some((block_pos, _)) = raycast_voxel(camera.translation, camera.forward(), REACH, |pos| {
+        block_registry.get(world.get_block(pos)).solid
+    }) else {
+        info!("Look at a block to export its chunk");
+        return;
+    };
+
+    let chunk_position = IVec2XZ::from_block(block_pos, world.config.chunk_dims());
+    let Some(mesh) = world
+        .chunks_loaded
+        .chunks
+        .get(&chunk_position)
+        .and_then(|&entity| mesh_query.get(entity).ok())
+        .and_then(|handle| meshes.get(handle))
+    else {
+        info!("The chunk isn't meshed yet");
+        return;
+    };
+
+    let mut mesh = mesh.clone();
+    convert_up_axis(&mut mesh, export_settings.up_axis);
+
+    let path = format!("chunk_{}_{}.obj", chunk_position.x, chunk_position.z);
+    match export_mesh_obj(&mesh, Path::new(&path)) {
+        Ok(()) => info!("Exported the chunk to {}", path),
+        Err(error) => error!("Failed to export the chunk to {}: {}", path, error),
+    }
+}
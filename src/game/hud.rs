@@ -3,15 +3,26 @@ use bevy::{
         DiagnosticsStore, FrameTimeDiagnosticsPlugin, SystemInformationDiagnosticsPlugin,
     },
     prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
     window::PresentMode,
 };
+use noise::NoiseFn;
 
 use super::common::*;
+use super::terrain::SharedTerrainNoise;
 
 // For FPS counter
 #[derive(Component)]
 pub struct TextChanges;
 
+/// The UI text showing the [`ChunkProgress`].
+#[derive(Component)]
+pub struct ChunkProgressText;
+
+/// The UI image showing a slice of the cave noise, see [`noise_image`].
+#[derive(Component)]
+pub struct NoiseOverlay;
+
 /// Updates the UI text.
 ///
 /// Information about the FPS, coordinates and direction is displayed.
@@ -21,6 +32,7 @@ pub fn update_text(
     camera_query: Query<&Transform, With<Camera>>,
     chunk_query: Query<&ChunkMesh>,
     windows: Query<&Window>,
+    generation_config: Res<GenerationConfig>,
 ) {
     // Update the FPS counter.
     let mut fps_text = query.single_mut();
@@ -50,10 +62,8 @@ pub fn update_text(
 
     // Update the coordinates and direction.
     let camera_transform = camera_query.single();
-    let camera_transform_chunks: IVec2XZ = IVec2XZ::new(
-        (camera_transform.translation.x / CHUNK_SIZE as f32).floor() as i32,
-        (camera_transform.translation.z / CHUNK_SIZE as f32).floor() as i32,
-    );
+    let camera_transform_chunks: IVec2XZ =
+        IVec2XZ::from_world(camera_transform.translation, generation_config.chunk_dims());
 
     let camera_position = camera_transform.translation;
     // Determine if the camera is looking towards +X, -X, +Z or -Z.
@@ -103,6 +113,19 @@ pub fn update_text(
     );
 }
 
+/// Updates the chunk loading progress text.
+pub fn update_chunk_progress_text(
+    chunk_progress: Res<ChunkProgress>,
+    mut query: Query<&mut Text, With<ChunkProgressText>>,
+) {
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "Chunks: {}/{}, meshing {}",
+            chunk_progress.loaded, chunk_progress.total, chunk_progress.meshing
+        );
+    }
+}
+
 pub fn setup_hud(mut commands: Commands) {
     // Manual implementation of the crosshair.
     // root node
@@ -169,9 +192,27 @@ pub fn setup_hud(mut commands: Commands) {
         TextChanges,
     ));
 
+    // Text to display the chunk loading progress
+    commands.spawn((
+        TextBundle::from_section(
+            "".to_string(),
+            TextStyle {
+                font_size: 20.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+        ChunkProgressText,
+    ));
+
     // Text to display controls
     commands.spawn((TextBundle::from_section(
-        "P - Pause Chunk generation\nR - Reset Chunks\nG - Toggle Chunks Borders\nV - Toggle VSync"
+        "P - Pause Chunk generation\nR - Reset Chunks\nG - Toggle Chunks Borders\nB - Toggle Loaded Chunks\nV - Toggle VSync\nF3 - Toggle Wireframe\nC - Toggle back faces\nLeft Click - Break block\nRight Click - Place block\n1-5 - Select block\nF5/F9 - Save/Load world\nF6 - Export chunk to OBJ\nF - Toggle walking\n+/- - Cave threshold\nM - Cycle meshing mode\nN - Next seed\nK - Cycle MSAA\nT - Suggest cave threshold"
             .to_string(),
         TextStyle {
             font_size: 20.0,
@@ -185,3 +226,82 @@ pub fn setup_hud(mut commands: Commands) {
         ..default()
     }),));
 }
+
+pub fn setup_noise_overlay(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    // Filled in by `update_noise_overlay`.
+    let image = images.add(Image::default());
+    commands.spawn((
+        ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                right: Val::Px(10.0),
+                width: Val::Px(NOISE_OVERLAY_SIZE as f32),
+                height: Val::Px(NOISE_OVERLAY_SIZE as f32),
+                ..default()
+            },
+            image: UiImage::new(image),
+            ..default()
+        },
+        NoiseOverlay,
+    ));
+}
+
+/// Redraws the noise overlay whenever the generation config changes, like a new seed or cave scale.
+pub fn update_noise_overlay(
+    generation_config: Res<GenerationConfig>,
+    terrain_noise: Res<SharedTerrainNoise>,
+    query: Query<&UiImage, With<NoiseOverlay>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !generation_config.is_changed() {
+        return;
+    }
+
+    let noise = terrain_noise.get(&generation_config);
+    for ui_image in query.iter() {
+        if let Some(image) = images.get_mut(&ui_image.texture) {
+            *image = noise_image(&noise.cave, &generation_config, NOISE_OVERLAY_SIZE);
+        }
+    }
+}
+
+/// Draws a top-down slice of the cave noise at the sea level, `size` blocks wide around the spawn center. The noise
+/// goes from black at -1 to white at 1, and the blocks it carves out are tinted red.
+pub fn noise_image(noise: &impl NoiseFn<f64, 3>, config: &GenerationConfig, size: u32) -> Image {
+    let corner = config.spawn_center - IVec3::new(size as i32 / 2, 0, size as i32 / 2);
+    let y = config.sea_level as f64 * config.cave_scale;
+
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for z in 0..size {
+        for x in 0..size {
+            let value = noise.get([
+                (corner.x + x as i32) as f64 * config.cave_scale,
+                y,
+                (corner.z + z as i32) as f64 * config.cave_scale,
+            ]);
+            let gray = noise_to_gray(value);
+            if value < config.cave_threshold {
+                data.extend_from_slice(&[gray, gray, gray, 255]);
+            } else {
+                data.extend_from_slice(&[gray, gray / 2, gray / 2, 255]);
+            }
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Maps a noise value from -1 to 1 onto a gray level from 0 to 255.
+pub fn noise_to_gray(value: f64) -> u8 {
+    ((value.clamp(-1.0, 1.0) + 1.0) * 0.5 * 255.0).round() as u8
+}
@@ -0,0 +1,111 @@
+use bevy::{
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+
+use super::common::*;
+use super::raycast::raycast_voxel;
+use super::world::VoxelWorld;
+
+/// Breaks the block the camera is looking at when the left mouse button is pressed.
+pub fn break_block(
+    mut commands: Commands,
+    mouse: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<&Transform, With<Camera3d>>,
+    mut world: VoxelWorld,
+    block_registry: Res<BlockRegistry>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) || !cursor_grabbed(&windows) {
+        return;
+    }
+
+    let camera = camera_query.single();
+    let Some((block_pos, _)) = raycast_voxel(camera.translation, camera.forward(), REACH, |pos| {
+        block_registry.get(world.get_block(pos)).solid
+    }) else {
+        return;
+    };
+    if !world.get_block(block_pos).is_breakable() {
+        return;
+    }
+
+    set_block(&mut commands, block_pos, BlockType::Air, &mut world);
+}
+
+/// Places the selected block against the face the camera is looking at when the right mouse button is pressed.
+pub fn place_block(
+    mut commands: Commands,
+    mouse: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<&Transform, With<Camera3d>>,
+    mut world: VoxelWorld,
+    selected_block: Res<SelectedBlock>,
+    block_registry: Res<BlockRegistry>,
+) {
+    if !mouse.just_pressed(MouseButton::Right) || !cursor_grabbed(&windows) {
+        return;
+    }
+
+    let camera = camera_query.single();
+    let Some((block_pos, face)) = raycast_voxel(camera.translation, camera.forward(), REACH, |pos| {
+        block_registry.get(world.get_block(pos)).solid
+    }) else {
+        return;
+    };
+
+    // The block in front of the hit face, which may be in the next chunk.
+    let target_pos = block_pos + face.offset();
+    if block_registry.get(world.get_block(target_pos)).solid {
+        return;
+    }
+
+    set_block(&mut commands, target_pos, selected_block.0, &mut world);
+}
+
+/// Picks the [`SelectedBlock`] from the [`HOTBAR`] with the number keys.
+pub fn select_block(keyboard: Res<Input<KeyCode>>, mut selected_block: ResMut<SelectedBlock>) {
+    let keys = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+    ];
+
+    for (key, block) in keys.into_iter().zip(HOTBAR) {
+        if keyboard.just_pressed(key) {
+            selected_block.0 = block;
+        }
+    }
+}
+
+/// The cursor is only grabbed while flying around, so clicks in the inspector don't edit the world.
+pub fn cursor_grabbed(windows: &Query<&Window, With<PrimaryWindow>>) -> bool {
+    windows
+        .get_single()
+        .is_ok_and(|window| window.cursor.grab_mode != CursorGrabMode::None)
+}
+
+/// Changes the block at the world position through the [`VoxelWorld`] and marks every chunk that can see it as a
+/// [`DirtyChunk`].
+fn set_block(commands: &mut Commands, pos: IVec3, block: BlockType, world: &mut VoxelWorld) {
+    let dims = world.config.chunk_dims();
+    if world_block_index(pos, dims).is_none() {
+        return;
+    }
+    world.set_block(pos, block);
+
+    // Blocks on the border of a chunk are also culled against by the chunk next to it.
+    let chunk_position = IVec2XZ::from_block(pos, dims);
+    let neighbor_positions = [BlockFace::Left, BlockFace::Right, BlockFace::Front, BlockFace::Back]
+        .map(|face| IVec2XZ::from_block(pos + face.offset(), dims))
+        .into_iter()
+        .filter(|&position| position != chunk_position);
+
+    for position in std::iter::once(chunk_position).chain(neighbor_positions) {
+        if let Some(&entity) = world.chunks_loaded.chunks.get(&position) {
+            commands.entity(entity).insert(DirtyChunk);
+        }
+    }
+}
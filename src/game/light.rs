@@ -0,0 +1,172 @@
+use bevy::{prelude::*, render::mesh::VertexAttributeValues};
+use std::collections::VecDeque;
+
+use super::common::*;
+
+/// The light level of every block of a chunk, indexed like the blocks with [`block_index`]. 0 is dark and
+/// [`MAX_LIGHT`] is as bright as a glowing block.
+pub struct LightGrid {
+    pub levels: Vec<u8>,
+    pub dims: ChunkDims,
+}
+
+impl LightGrid {
+    /// Returns the light level at the local position. Positions outside of the chunk take the light of the closest
+    /// block inside it, which is close enough until the light spreads across chunks.
+    pub fn level(&self, local_pos: IVec3) -> u8 {
+        let max = IVec3::new(self.dims.x as i32, self.dims.y as i32, self.dims.z as i32) - 1;
+        let pos = local_pos.clamp(IVec3::ZERO, max);
+        self.levels[block_index(pos.x as usize, pos.y as usize, pos.z as usize, self.dims)]
+    }
+
+    /// Combines two kinds of light of the same chunk, every block keeps the brighter one.
+    pub fn max(mut self, other: LightGrid) -> LightGrid {
+        for (level, other_level) in self.levels.iter_mut().zip(other.levels) {
+            *level = (*level).max(other_level);
+        }
+        self
+    }
+}
+
+/// Lights every column of the chunk from the sky down to its first opaque block, everything below stays dark. That
+/// makes caves and the ground under overhangs dark even without the sun. The light loses a level in every translucent
+/// block, so deep water gets darker.
+pub fn compute_skylight(
+    chunk_blocks: &[BlockType],
+    dims: ChunkDims,
+    registry: &BlockRegistry,
+) -> LightGrid {
+    let mut levels = vec![0; dims.volume()];
+
+    for x in 0..dims.x {
+        for z in 0..dims.z {
+            let mut level = MAX_LIGHT;
+            for y in (0..dims.y).rev() {
+                let index = block_index(x, y, z, dims);
+                let info = registry.get(chunk_blocks[index]);
+                if !info.transparent {
+                    break;
+                }
+                if info.translucent {
+                    level = level.saturating_sub(1);
+                }
+                levels[index] = level;
+            }
+        }
+    }
+
+    LightGrid { levels, dims }
+}
+
+/// Spreads the light of the glowing blocks through the chunk. The light loses a level with every block it travels
+/// and only goes through the transparent blocks, so it flows around walls.
+///
+/// Only the blocks of the chunk are lit, the light doesn't spread into the neighbouring chunks yet.
+pub fn propagate_block_light(
+    chunk_blocks: &[BlockType],
+    dims: ChunkDims,
+    registry: &BlockRegistry,
+) -> LightGrid {
+    let mut levels = vec![0; dims.volume()];
+    let mut queue = VecDeque::new();
+
+    for (index, &block) in chunk_blocks.iter().enumerate() {
+        if registry.get(block).emissive != Color::BLACK {
+            levels[index] = MAX_LIGHT;
+            queue.push_back(index);
+        }
+    }
+
+    // Breadth first, so every block is reached from the closest light first and only visited once per level.
+    while let Some(index) = queue.pop_front() {
+        let level = levels[index];
+        if level <= 1 {
+            continue;
+        }
+
+        let (x, y, z) = block_position(index, dims);
+        let pos = IVec3::new(x as i32, y as i32, z as i32);
+        for face in BlockFace::ALL {
+            let neighbor = pos + face.offset();
+            let Some(neighbor_index) = local_block_index(neighbor, dims) else {
+                continue;
+            };
+            if registry.get(chunk_blocks[neighbor_index]).transparent
+                && levels[neighbor_index] < level - 1
+            {
+                levels[neighbor_index] = level - 1;
+                queue.push_back(neighbor_index);
+            }
+        }
+    }
+
+    LightGrid { levels, dims }
+}
+
+/// How bright a light level makes a face, from almost black at 0 to fully lit at [`MAX_LIGHT`].
+pub fn light_brightness(level: u8) -> f32 {
+    LIGHT_FALLOFF.powi((MAX_LIGHT - level.min(MAX_LIGHT)) as i32)
+}
+
+/// Darkens the vertex colors of a mesh of the chunk at `chunk_position` by the light in front of every corner. Each
+/// corner takes the brightest of the four blocks it touches in the layer in front of its face, so the light fades
+/// smoothly.
+///
+/// The vertices have to be in world space, see [`ChunkMeshData`](super::chunk::ChunkMeshData).
+pub fn apply_light(mesh: &mut Mesh, light: &LightGrid, chunk_position: IVec2XZ) {
+    let origin = chunk_position.origin(light.dims);
+
+    let (
+        Some(VertexAttributeValues::Float32x3(positions)),
+        Some(VertexAttributeValues::Float32x3(normals)),
+    ) = (
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+        mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+    )
+    else {
+        return;
+    };
+
+    let brightness: Vec<f32> = positions
+        .iter()
+        .zip(normals)
+        .map(|(position, normal)| {
+            let corner = Vec3::from_array(*position).round().as_ivec3();
+            let normal = Vec3::from_array(*normal).round().as_ivec3();
+            let axis = if normal.x != 0 {
+                0
+            } else if normal.y != 0 {
+                1
+            } else {
+                2
+            };
+            let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+
+            // The blocks in front of the face that share the corner.
+            let mut front = corner;
+            front[axis] += normal[axis].min(0);
+            let level = [(0, 0), (-1, 0), (0, -1), (-1, -1)]
+                .into_iter()
+                .map(|(u, v)| {
+                    let mut block = front;
+                    block[u_axis] += u;
+                    block[v_axis] += v;
+                    light.level(block - origin)
+                })
+                .max()
+                .unwrap_or(0);
+
+            light_brightness(level)
+        })
+        .collect();
+
+    if let Some(VertexAttributeValues::Float32x4(colors)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_COLOR)
+    {
+        for (color, brightness) in colors.iter_mut().zip(brightness) {
+            for channel in &mut color[..3] {
+                *channel *= brightness;
+            }
+        }
+    }
+}
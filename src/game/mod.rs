@@ -1,5 +1,15 @@
-pub mod camera;
 pub mod chunk;
 pub mod common;
+pub mod coords;
 pub mod debug;
+pub mod export;
 pub mod hud;
+pub mod interaction;
+pub mod light;
+pub mod player;
+pub mod raycast;
+pub mod save;
+pub mod sky;
+pub mod terrain;
+pub mod texture_array;
+pub mod world;
@@ -0,0 +1,149 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_flycam::prelude::MovementSettings;
+
+use super::common::*;
+use super::interaction::cursor_grabbed;
+
+/// Switches between flying and walking with F. The fly camera stands still while walking, [`player_physics`] moves
+/// it instead.
+pub fn walk_mode_keyboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut walk_mode: ResMut<WalkMode>,
+    mut movement_settings: ResMut<MovementSettings>,
+    mut player_query: Query<&mut PlayerPhysics>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F) {
+        return;
+    }
+
+    walk_mode.0 = !walk_mode.0;
+    movement_settings.speed = if walk_mode.0 { 0. } else { FLY_SPEED };
+
+    // Start falling from a standstill.
+    for mut physics in player_query.iter_mut() {
+        *physics = PlayerPhysics::default();
+    }
+    info!("Walk mode: {}", walk_mode.0);
+}
+
+/// Walks the camera with WASD and Space while in [`WalkMode`], pulling it down with gravity and stopping it at solid
+/// blocks.
+#[allow(clippy::too_many_arguments)]
+pub fn player_physics(
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    walk_mode: Res<WalkMode>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut player_query: Query<(&mut Transform, &mut PlayerPhysics)>,
+    chunks_loaded: Res<ChunksLoaded>,
+    chunk_data: Query<&ChunkData>,
+    generation_config: Res<GenerationConfig>,
+    block_registry: Res<BlockRegistry>,
+) {
+    if !walk_mode.0 {
+        return;
+    }
+
+    let dims = generation_config.chunk_dims();
+    // Chunks that are still generating count as solid, so the player waits on top of them instead of falling out of
+    // the world. Above and below the world is empty.
+    let solid = |pos: IVec3| {
+        let Some(index) = world_block_index(pos, dims) else {
+            return false;
+        };
+        chunks_loaded
+            .chunks
+            .get(&IVec2XZ::from_block(pos, dims))
+            .and_then(|&entity| chunk_data.get(entity).ok())
+            .map_or(true, |data| block_registry.get(data.0[index]).solid)
+    };
+
+    for (mut transform, mut physics) in player_query.iter_mut() {
+        // Walk along the ground in the direction the camera is looking.
+        let mut input = Vec3::ZERO;
+        if cursor_grabbed(&windows) {
+            let forward = (transform.forward() * Vec3::new(1., 0., 1.)).normalize_or_zero();
+            let right = (transform.right() * Vec3::new(1., 0., 1.)).normalize_or_zero();
+            for (key, direction) in [
+                (KeyCode::W, forward),
+                (KeyCode::S, -forward),
+                (KeyCode::D, right),
+                (KeyCode::A, -right),
+            ] {
+                if keyboard_input.pressed(key) {
+                    input += direction;
+                }
+            }
+
+            if keyboard_input.pressed(KeyCode::Space) && physics.on_ground {
+                physics.velocity.y = JUMP_SPEED;
+            }
+        }
+
+        let walk_velocity = input.normalize_or_zero() * WALK_SPEED;
+        physics.velocity.x = walk_velocity.x;
+        physics.velocity.z = walk_velocity.z;
+        physics.velocity.y =
+            (physics.velocity.y - GRAVITY * time.delta_seconds()).max(-TERMINAL_VELOCITY);
+
+        // Move in steps of less than a block, so a slow frame can't tunnel through the ground.
+        let delta = physics.velocity * time.delta_seconds();
+        let steps = (delta.abs().max_element() / 0.5).ceil().max(1.);
+        let step = delta / steps;
+
+        physics.on_ground = false;
+        let mut eye = transform.translation;
+        for _ in 0..steps as u32 {
+            // One axis at a time, so the player slides along the walls.
+            for axis in 0..3 {
+                eye[axis] += step[axis];
+
+                if let Some(resolved) = resolve_collision(eye, axis, step[axis], &solid) {
+                    eye[axis] = resolved;
+                    physics.velocity[axis] = 0.;
+                    if axis == 1 && step[axis] < 0. {
+                        physics.on_ground = true;
+                    }
+                }
+            }
+        }
+        transform.translation = eye;
+    }
+}
+
+/// Returns the bottom and top corners of the player's box, with the camera at `eye`.
+pub fn player_aabb(eye: Vec3) -> (Vec3, Vec3) {
+    (
+        eye - Vec3::new(PLAYER_RADIUS, PLAYER_EYE_HEIGHT, PLAYER_RADIUS),
+        eye + Vec3::new(PLAYER_RADIUS, PLAYER_HEAD_HEIGHT, PLAYER_RADIUS),
+    )
+}
+
+/// Checks if the player's box overlaps a solid block after moving `step` along the axis. If it does, returns the
+/// coordinate on that axis that puts the box right against the block it moved into.
+fn resolve_collision(
+    eye: Vec3,
+    axis: usize,
+    step: f32,
+    solid: &impl Fn(IVec3) -> bool,
+) -> Option<f32> {
+    let (min, max) = player_aabb(eye);
+    let (min_block, max_block) = (min.floor().as_ivec3(), max.ceil().as_ivec3() - IVec3::ONE);
+
+    let overlaps = (min_block.x..=max_block.x).any(|x| {
+        (min_block.y..=max_block.y)
+            .any(|y| (min_block.z..=max_block.z).any(|z| solid(IVec3::new(x, y, z))))
+    });
+    if !overlaps {
+        return None;
+    }
+
+    // The box was free before this step, so only the side it moved towards can be in a block.
+    if step > 0. {
+        Some(max[axis].floor() - (max[axis] - eye[axis]))
+    } else if step < 0. {
+        Some(min[axis].floor() + 1. + (eye[axis] - min[axis]))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,83 @@
+use bevy::prelude::*;
+
+use super::common::*;
+
+/// Walks the blocks along a ray and returns the first block for which `is_solid` is true, and the face the ray entered it through.
+///
+/// This is the voxel traversal from Amanatides & Woo, so no block along the ray is skipped. The block containing
+/// `origin` isn't checked.
+pub fn raycast_voxel(
+    origin: Vec3,
+    dir: Vec3,
+    max_dist: f32,
+    is_solid: impl Fn(IVec3) -> bool,
+) -> Option<(IVec3, BlockFace)> {
+    let dir = dir.normalize_or_zero();
+    if dir == Vec3::ZERO {
+        return None;
+    }
+
+    let mut block = origin.floor().as_ivec3();
+
+    // Which way the ray goes on each axis.
+    let step = IVec3::new(axis_step(dir.x), axis_step(dir.y), axis_step(dir.z));
+
+    // How far along the ray we have to go to cross one block on each axis.
+    let t_delta = (1. / dir).abs();
+
+    // How far along the ray the next block border is on each axis.
+    let mut t_max = Vec3::INFINITY;
+    for axis in 0..3 {
+        if step[axis] > 0 {
+            t_max[axis] = (block[axis] as f32 + 1. - origin[axis]) * t_delta[axis];
+        } else if step[axis] < 0 {
+            t_max[axis] = (origin[axis] - block[axis] as f32) * t_delta[axis];
+        }
+    }
+
+    loop {
+        // Cross the closest border.
+        let axis = if t_max.x < t_max.y {
+            if t_max.x < t_max.z {
+                0
+            } else {
+                2
+            }
+        } else if t_max.y < t_max.z {
+            1
+        } else {
+            2
+        };
+
+        if t_max[axis] > max_dist {
+            return None;
+        }
+
+        block[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+
+        if is_solid(block) {
+            // The ray comes in through the face looking back at it.
+            let face = match (axis, step[axis] > 0) {
+                (0, true) => BlockFace::Left,
+                (0, false) => BlockFace::Right,
+                (1, true) => BlockFace::Bottom,
+                (1, false) => BlockFace::Top,
+                (_, true) => BlockFace::Back,
+                (_, false) => BlockFace::Front,
+            };
+
+            return Some((block, face));
+        }
+    }
+}
+
+fn axis_step(dir: f32) -> i32 {
+    if dir > 0. {
+        1
+    } else if dir < 0. {
+        -1
+    } else {
+        0
+    }
+}
@@ -0,0 +1,151 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+use super::common::*;
+use super::terrain::{generate_chunk_blocks, TerrainNoise};
+
+/// A saved world. Chunks that aren't in it are generated from the config again, so only the edited ones are stored.
+#[derive(Serialize, Deserialize)]
+pub struct WorldSave {
+    pub config: GenerationConfig,
+    /// Every block of the chunks the player changed, indexed with [`block_index`] and run-length encoded.
+    pub chunks: HashMap<IVec2XZ, Vec<(BlockType, u32)>>,
+}
+
+impl WorldSave {
+    /// Turns the saved chunks back into overrides by comparing them with freshly generated chunks.
+    pub fn modified_chunks(&self) -> ModifiedChunks {
+        let noise = TerrainNoise::new(&self.config);
+
+        let chunks = self
+            .chunks
+            .iter()
+            .map(|(&chunk_position, runs)| {
+                let generated = generate_chunk_blocks(chunk_position, &noise, &self.config);
+                let overrides = rle_decode(runs)
+                    .into_iter()
+                    .zip(generated)
+                    .enumerate()
+                    .filter(|(_, (saved, generated))| saved != generated)
+                    .map(|(index, (saved, _))| (index, saved))
+                    .collect();
+
+                (chunk_position, overrides)
+            })
+            .collect();
+
+        ModifiedChunks {
+            chunks,
+            dims: self.config.chunk_dims(),
+        }
+    }
+}
+
+/// Compresses blocks into `(block, count)` runs. Chunks are mostly long runs of air above and stone below.
+pub fn rle_encode(data: &[BlockType]) -> Vec<(BlockType, u32)> {
+    let mut runs: Vec<(BlockType, u32)> = Vec::new();
+
+    for &block in data {
+        match runs.last_mut() {
+            Some((run_block, count)) if *run_block == block => *count += 1,
+            _ => runs.push((block, 1)),
+        }
+    }
+
+    runs
+}
+
+/// Expands runs made by [`rle_encode`] back into blocks.
+pub fn rle_decode(runs: &[(BlockType, u32)]) -> Vec<BlockType> {
+    runs.iter()
+        .flat_map(|&(block, count)| std::iter::repeat(block).take(count as usize))
+        .collect()
+}
+
+pub fn save_world(path: &Path, save: &WorldSave) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    bincode::serialize_into(writer, save)
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error))
+}
+
+pub fn load_world(path: &Path) -> io::Result<WorldSave> {
+    let reader = BufReader::new(File::open(path)?);
+    bincode::deserialize_from(reader)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Saves the world to [`SAVE_PATH`] with F5 and loads it back with F9.
+pub fn save_keyboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    chunks_loaded: Res<ChunksLoaded>,
+    chunk_data: Query<&ChunkData>,
+    mut modified_chunks: ResMut<ModifiedChunks>,
+    mut generation_config: ResMut<GenerationConfig>,
+) {
+    let path = Path::new(SAVE_PATH);
+
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        let noise = TerrainNoise::new(&generation_config);
+
+        // Loaded chunks already have their blocks, the others are generated again.
+        let chunks = modified_chunks
+            .chunks
+            .keys()
+            .map(|&chunk_position| {
+                let loaded_blocks = chunks_loaded
+                    .chunks
+                    .get(&chunk_position)
+                    .and_then(|&entity| chunk_data.get(entity).ok())
+                    .map(|data| data.0.clone());
+
+                let chunk_blocks = loaded_blocks.unwrap_or_else(|| {
+                    let mut chunk_blocks =
+                        generate_chunk_blocks(chunk_position, &noise, &generation_config);
+                    modified_chunks.apply(chunk_position, &mut chunk_blocks);
+                    chunk_blocks
+                });
+
+                (chunk_position, rle_encode(&chunk_blocks))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let raw_size =
+            chunks.len() * generation_config.chunk_volume() * std::mem::size_of::<BlockType>();
+        let encoded_size = chunks.values().map(Vec::len).sum::<usize>()
+            * std::mem::size_of::<(BlockType, u32)>();
+        info!(
+            "Run-length encoded {} chunks: {} -> {} bytes ({:.1}x smaller)",
+            chunks.len(),
+            raw_size,
+            encoded_size,
+            raw_size as f32 / encoded_size.max(1) as f32
+        );
+
+        let save = WorldSave {
+            config: *generation_config,
+            chunks,
+        };
+        match save_world(path, &save) {
+            Ok(()) => info!("Saved {} chunks to {}", save.chunks.len(), SAVE_PATH),
+            Err(error) => error!("Failed to save the world to {}: {}", SAVE_PATH, error),
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        match load_world(path) {
+            Ok(save) => {
+                *modified_chunks = save.modified_chunks();
+                // Changing the config regenerates every loaded chunk, now with the loaded edits.
+                *generation_config = save.config;
+                info!("Loaded {} chunks from {}", save.chunks.len(), SAVE_PATH);
+            }
+            Err(error) => error!("Failed to load the world from {}: {}", SAVE_PATH, error),
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use bevy::prelude::*;
+
+use super::common::*;
+
+/// Applies the [`FogDistance`] to the camera whenever it's changed, e.g. in the inspector.
+pub fn update_fog(fog_distance: Res<FogDistance>, mut fog_query: Query<&mut FogSettings>) {
+    if !fog_distance.is_changed() {
+        return;
+    }
+
+    for mut fog in fog_query.iter_mut() {
+        fog.falloff = FogFalloff::Linear {
+            start: fog_distance.start,
+            end: fog_distance.end,
+        };
+    }
+}
@@ -0,0 +1,622 @@
+use bevy::prelude::*;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin, RidgedMulti};
+use std::sync::Arc;
+
+use super::common::*;
+use super::coords::chunk_local_to_world;
+
+/// Generates the blocks of a chunk. Nothing is meshed here.
+///
+/// The blocks are stored in a flat vector, use [`block_index`] to get a block.
+pub fn generate_chunk_blocks(
+    chunk_position: IVec2XZ,
+    noise: &TerrainNoise,
+    config: &GenerationConfig,
+) -> Vec<BlockType> {
+    // Loop over each block position in the chunk.
+    (0..config.chunk_volume())
+        .map(|index| generate_block(chunk_position, index, noise, config))
+        .collect()
+}
+
+/// Same as [`generate_chunk_blocks`], but every block is sampled on the rayon thread pool.
+#[cfg(feature = "parallel")]
+pub fn generate_chunk_blocks_parallel(
+    chunk_position: IVec2XZ,
+    noise: &TerrainNoise,
+    config: &GenerationConfig,
+) -> Vec<BlockType> {
+    use rayon::prelude::*;
+
+    (0..config.chunk_volume())
+        .into_par_iter()
+        .map(|index| generate_block(chunk_position, index, noise, config))
+        .collect()
+}
+
+/// Generates the block at the index of the flat chunk data.
+fn generate_block(
+    chunk_position: IVec2XZ,
+    index: usize,
+    noise: &TerrainNoise,
+    config: &GenerationConfig,
+) -> BlockType {
+    let (x, y, z) = block_position(index, config.chunk_dims());
+
+    // Remember to offset the position by the chunk position.
+    let local = IVec3::new(x as i32, y as i32, z as i32);
+    let pos = chunk_local_to_world(chunk_position, local, config.chunk_dims());
+
+    // Sample the noise function at the scaled position.
+    is_block(pos, noise, config)
+}
+
+/// A 2D noise function that can be picked at runtime, see [`SurfaceNoise`].
+pub type BoxedNoise2D = Box<dyn NoiseFn<f64, 2> + Send + Sync>;
+
+/// The noise functions used by the terrain generation.
+pub struct TerrainNoise {
+    /// Used for the ores.
+    pub surface: Perlin,
+    /// Used for the surface height.
+    pub height: BoxedNoise2D,
+    /// Used to carve the caves. It has its own seed, so the caves don't line up with the surface.
+    pub cave: Perlin,
+    /// Used to pick the biomes.
+    pub biome: Perlin,
+    /// Used for the density of [`TerrainMode::Ridged3D`], `None` in the other modes.
+    pub ridged: Option<RidgedMulti<Perlin>>,
+}
+
+impl TerrainNoise {
+    pub fn new(config: &GenerationConfig) -> Self {
+        Self {
+            surface: Perlin::new(config.seed),
+            height: height_noise(config),
+            cave: Perlin::new(config.seed ^ CAVE_SEED_SALT),
+            biome: Perlin::new(config.seed ^ BIOME_SEED_SALT),
+            ridged: ridged_octaves(config).map(|octaves| {
+                RidgedMulti::<Perlin>::new(config.seed ^ RIDGED_SEED_SALT)
+                    .set_octaves(octaves.max(1) as usize)
+            }),
+        }
+    }
+}
+
+/// The [`TerrainNoise`] of the [`GenerationConfig`], shared by all the generation tasks instead of each of them
+/// building its own. Building the noise seeds and allocates the permutation table of every noise function.
+#[derive(Resource, Clone)]
+pub struct SharedTerrainNoise {
+    noise: Arc<TerrainNoise>,
+    /// The config the noise was built from.
+    config: GenerationConfig,
+}
+
+impl SharedTerrainNoise {
+    pub fn new(config: &GenerationConfig) -> Self {
+        Self {
+            noise: Arc::new(TerrainNoise::new(config)),
+            config: *config,
+        }
+    }
+
+    /// Returns the noise of the config. A new one is only built if the config needs a different noise than the one
+    /// from the last [`SharedTerrainNoise::update`], so a new seed is never sampled with the old noise.
+    pub fn get(&self, config: &GenerationConfig) -> Arc<TerrainNoise> {
+        if same_noise(&self.config, config) {
+            self.noise.clone()
+        } else {
+            Arc::new(TerrainNoise::new(config))
+        }
+    }
+
+    /// Rebuilds the noise if the config needs a different one, like after a seed change.
+    pub fn update(&mut self, config: &GenerationConfig) {
+        if !same_noise(&self.config, config) {
+            *self = Self::new(config);
+        }
+    }
+}
+
+impl Default for SharedTerrainNoise {
+    fn default() -> Self {
+        Self::new(&GenerationConfig::default())
+    }
+}
+
+/// Whether the noise built from both configs is the same. The other settings only change how it's sampled.
+fn same_noise(a: &GenerationConfig, b: &GenerationConfig) -> bool {
+    a.seed == b.seed
+        && a.surface_noise == b.surface_noise
+        && a.octaves == b.octaves
+        && a.lacunarity == b.lacunarity
+        && a.persistence == b.persistence
+        && ridged_octaves(a) == ridged_octaves(b)
+}
+
+/// The octaves of the ridged noise, if the config uses [`TerrainMode::Ridged3D`].
+fn ridged_octaves(config: &GenerationConfig) -> Option<u32> {
+    match config.terrain_mode {
+        TerrainMode::Ridged3D { octaves, .. } => Some(octaves),
+        _ => None,
+    }
+}
+
+/// Builds the [`SurfaceNoise`] picked in the config.
+fn height_noise(config: &GenerationConfig) -> BoxedNoise2D {
+    let octaves = config.octaves.max(1);
+
+    match config.surface_noise {
+        SurfaceNoise::Classic => Box::new(ClassicOctaves {
+            perlin: Perlin::new(config.seed),
+            octaves,
+        }),
+        SurfaceNoise::Perlin => Box::new(PerlinOctaves {
+            perlin: Perlin::new(config.seed),
+            octaves,
+            lacunarity: config.lacunarity,
+            persistence: config.persistence,
+        }),
+        SurfaceNoise::Fbm => Box::new(
+            Fbm::<Perlin>::new(config.seed)
+                .set_octaves(octaves as usize)
+                .set_lacunarity(config.lacunarity)
+                .set_persistence(config.persistence),
+        ),
+        SurfaceNoise::Ridged => Box::new(
+            RidgedMulti::<Perlin>::new(config.seed)
+                .set_octaves(octaves as usize)
+                .set_lacunarity(config.lacunarity)
+                .set_persistence(config.persistence),
+        ),
+    }
+}
+
+/// The octaves of [`SurfaceNoise::Classic`]. The frequencies grow by the base frequency instead of doubling like in
+/// [`fbm`], and the sum is normalized the same way.
+struct ClassicOctaves {
+    perlin: Perlin,
+    octaves: u32,
+}
+
+impl NoiseFn<f64, 2> for ClassicOctaves {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        let sum: f64 = (1..=self.octaves)
+            .map(|octave| self.perlin.get(point.map(|p| p * octave as f64)))
+            .sum();
+
+        sum / self.octaves as f64
+    }
+}
+
+/// [`fbm`] as a noise function.
+struct PerlinOctaves {
+    perlin: Perlin,
+    octaves: u32,
+    lacunarity: f64,
+    persistence: f64,
+}
+
+impl NoiseFn<f64, 2> for PerlinOctaves {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        fbm(
+            &self.perlin,
+            point,
+            self.octaves,
+            self.lacunarity,
+            self.persistence,
+        )
+    }
+}
+
+fn surface_generation(pos: IVec3, noise: &TerrainNoise, config: &GenerationConfig) -> BlockType {
+    let height = surface_height(pos, noise, config);
+    let biome = biome_at(pos.x, pos.z, noise, config);
+
+    // calculate block type given block position and height
+    match pos.y {
+        y if y == 0 => BlockType::Bedrock,
+        // y if y + 3 < height as i32 => BlockType::Stone,
+        y if y + (config.surface_depth as i32) < height as i32 => cave_block(pos, noise),
+        y if y < height as i32 && !(y > 63 && y < 72) && y > 64 => biome.filler_block(),
+        y if y == height as i32 && !(y > 63 && y < 72) && y > 64 => biome.surface_block(),
+        // y if y <= height as i32 && y == 6 => cave_generation(pos, perlin, true),
+        y if !y <= height as i32 && y < 64 => BlockType::Stone,
+        y if y <= height as i32 && (y > 63 && y < 72) => BlockType::Sand,
+        y if !y <= height as i32 && y == 64 => BlockType::Sand,
+        // y if y > 64 && y <= WATER_HEIGHT as i32 => BlockType::Water,
+        y if !y <= height as i32 && y <= config.sea_level as i32 => BlockType::Water,
+        _ => BlockType::Air,
+    }
+}
+
+/// Fills the column of the block up to the surface, without any caves or overhangs.
+fn heightmap_generation(pos: IVec3, noise: &TerrainNoise, config: &GenerationConfig) -> BlockType {
+    let height = surface_height(pos, noise, config) as i32;
+    let biome = biome_at(pos.x, pos.z, noise, config);
+
+    match pos.y {
+        y if y + (config.surface_depth as i32) < height => BlockType::Stone,
+        y if y < height => biome.filler_block(),
+        y if y == height => biome.surface_block(),
+        y if y <= config.sea_level as i32 => BlockType::Water,
+        _ => BlockType::Air,
+    }
+}
+
+/// Fills the blocks where the ridged noise plus the distance below the surface is positive. Far below the surface
+/// everything is filled and far above it nothing is, the noise only decides within [`RIDGED_FALLOFF`] blocks of it.
+fn ridged_generation(
+    pos: IVec3,
+    frequency: f64,
+    noise: &TerrainNoise,
+    config: &GenerationConfig,
+) -> BlockType {
+    let Some(ridged) = &noise.ridged else {
+        return heightmap_generation(pos, noise, config);
+    };
+    let height = surface_height(pos, noise, config);
+    let biome = biome_at(pos.x, pos.z, noise, config);
+
+    let ridges = ridged.get(pos.as_dvec3().to_array().map(|p| p * frequency)) as f32;
+    let density = ridges + (height - pos.y as f32) / RIDGED_FALLOFF;
+    if density <= 0. {
+        return if pos.y <= config.sea_level as i32 {
+            BlockType::Water
+        } else {
+            BlockType::Air
+        };
+    }
+
+    // The overhangs above the surface get its top block too, so they don't stick out as bare stone.
+    match pos.y {
+        y if y + (config.surface_depth as i32) < height as i32 => cave_block(pos, noise),
+        y if y < height as i32 => biome.filler_block(),
+        _ => biome.surface_block(),
+    }
+}
+
+/// Fills the world up to the ground height, with grass on top.
+fn flat_generation(pos: IVec3, ground_height: u32) -> BlockType {
+    match pos.y {
+        y if y + 1 < ground_height as i32 => BlockType::Stone,
+        y if y < ground_height as i32 => BlockType::Grass,
+        _ => BlockType::Air,
+    }
+}
+
+/// Fills a rough ball around the center, with a layer of dirt and grass on its surface.
+fn sphere_generation(
+    pos: IVec3,
+    center: IVec3,
+    radius: f32,
+    noise: &TerrainNoise,
+    config: &GenerationConfig,
+) -> BlockType {
+    let offset = (pos - center).as_vec3();
+
+    // The noise only depends on the direction from the center, so the bumps go straight out like mountains.
+    let direction = offset.normalize_or_zero().as_dvec3() * 4.;
+    let bump = noise.surface.get(direction.to_array()) as f32 * SPHERE_ROUGHNESS;
+
+    let depth = radius + bump - offset.length();
+    if depth <= 0. {
+        BlockType::Air
+    } else if depth <= 1. {
+        BlockType::Grass
+    } else if depth <= 1. + config.surface_depth as f32 {
+        BlockType::Dirt
+    } else {
+        BlockType::Stone
+    }
+}
+
+/// Only the surface block of the column, everything else is air. See [`GenerationConfig::preview`].
+fn preview_generation(pos: IVec3, noise: &TerrainNoise, config: &GenerationConfig) -> BlockType {
+    if pos.y == surface_height(pos, noise, config) as i32 {
+        biome_at(pos.x, pos.z, noise, config).surface_block()
+    } else {
+        BlockType::Air
+    }
+}
+
+/// Returns the height of the surface in the column of the block.
+fn surface_height(pos: IVec3, noise: &TerrainNoise, config: &GenerationConfig) -> f32 {
+    // Sample the noise function at the scaled position.
+    // The perlin noise needs a float value, so we need to cast the scaled position to a float.
+
+    // 2d noise, made of several octaves to make the terrain even more interesting
+    let noise_value = noise.height.get([
+        pos.x as f64 * 2. * config.surface_scale,
+        pos.z as f64 * 2. * config.surface_scale,
+    ]);
+
+    // The biomes only differ in how high they go, so the border between them is a smooth slope.
+    let max_height = lerp(
+        config.plains_height,
+        config.mountains_height,
+        biome_weight(pos.x, pos.z, noise, config),
+    );
+
+    // Change values (-1, 1) -> (TERRAIN_HEIGHT, MAX_HEIGHT)
+    // The range is lopsided to keep most of the terrain low (the same as (-1, 6) for 3 summed octaves)
+    remap(
+        noise_value as f32,
+        -1. / 3., //-1.
+        2.,       //1.
+        BLEND_HEIGHT as f32,
+        max_height,
+    )
+}
+
+/// Returns the biome of the column at the world position.
+pub fn biome_at(x: i32, z: i32, noise: &TerrainNoise, config: &GenerationConfig) -> Biome {
+    if biome_weight(x, z, noise, config) < 0.5 {
+        Biome::Plains
+    } else {
+        Biome::Mountains
+    }
+}
+
+/// How much the column belongs to the mountains, from 0 in the plains to 1 in the mountains.
+fn biome_weight(x: i32, z: i32, noise: &TerrainNoise, config: &GenerationConfig) -> f32 {
+    let biome_value = noise.biome.get([
+        x as f64 * config.biome_scale,
+        z as f64 * config.biome_scale,
+    ]);
+
+    let blend = config.biome_blend.max(f64::EPSILON);
+    smoothstep(remap(biome_value as f32, -blend as f32, blend as f32, 0., 1.))
+}
+
+fn cave_generation(pos: IVec3, noise: &TerrainNoise, config: &GenerationConfig) -> BlockType {
+    // Sample the noise function at the scaled position.
+    // The perlin noise needs a float value, so we need to cast the scaled position to a float.
+
+    // 3d perlin noise
+    let cave_noise_value = noise.cave.get([
+        pos.x as f64 * config.cave_scale,
+        pos.y as f64 * config.cave_scale,
+        pos.z as f64 * config.cave_scale,
+    ]);
+
+
+    // //
+    let height = surface_height(pos, noise, config);
+
+    let no_ocean: bool = pos.y + 10 < height as i32;
+    // //
+
+    if cave_noise_value < config.cave_threshold || !no_ocean {
+        if !(cave_noise_value < config.cave_threshold || pos.y > 62 && pos.y < 70) {
+            BlockType::Air
+        } else {
+            cave_block(pos, noise)
+        }
+    } else {
+        BlockType::Air
+    }
+}
+
+fn cave_block(pos: IVec3, noise: &TerrainNoise) -> BlockType {
+    let noise_ore_generation = noise.surface.get([
+        pos.x as f64 * ORE_SCALE,
+        pos.y as f64 * ORE_SCALE,
+        pos.z as f64 * ORE_SCALE,
+    ]);
+
+    // Check if the noise value is above the threshhold
+    // if (0.01..0.9).contains(&noise_bedrock_generation) && pos.y <= 4 {
+    if DIAMOND_THRESHOLD.contains(&noise_ore_generation) && pos.y <= 16 {
+        BlockType::DiamondOre
+    } else if REDSTONE_THRESHOLD.contains(&noise_ore_generation) && pos.y <= 28 && pos.y >= 6 {
+        BlockType::RedstoneOre
+    } else if GOLD_THRESHOLD.contains(&noise_ore_generation) && pos.y <= 24 && pos.y >= 6 {
+        BlockType::GoldOre
+    } else if IRON_THRESHOLD.contains(&noise_ore_generation) && pos.y <= 70 && pos.y >= 8 {
+        BlockType::IronOre
+    } else if COAL_THRESHOLD.contains(&noise_ore_generation) && pos.y <= 78 && pos.y >= 24 {
+        BlockType::CoalOre
+    } else {
+        BlockType::Stone
+    }
+}
+
+/// Samples about `samples` blocks spread over the ground around the spawn center, from the bottom of the world up to
+/// the sea level, and returns the fraction of them that is solid. The blocks come from [`is_block`], the same as in
+/// the real generation.
+pub fn estimate_solid_fraction(config: &GenerationConfig, samples: u32) -> f64 {
+    solid_fraction(&TerrainNoise::new(config), config, samples)
+}
+
+/// Searches for the cave threshold that makes `target` of the blocks solid, see [`estimate_solid_fraction`]. A higher
+/// threshold carves less, so the fraction only grows with it.
+pub fn suggest_cave_threshold(config: &GenerationConfig, target: f64, samples: u32) -> f64 {
+    let noise = TerrainNoise::new(config);
+    let mut config = *config;
+
+    // The noise never leaves (-1, 1).
+    let (mut low, mut high) = (-1., 1.);
+    for _ in 0..16 {
+        config.cave_threshold = (low + high) / 2.;
+        if solid_fraction(&noise, &config, samples) < target {
+            low = config.cave_threshold;
+        } else {
+            high = config.cave_threshold;
+        }
+    }
+
+    (low + high) / 2.
+}
+
+fn solid_fraction(noise: &TerrainNoise, config: &GenerationConfig, samples: u32) -> f64 {
+    // A grid of blocks over a few chunks around the spawn center.
+    let side = (samples as f64).cbrt().ceil().max(1.) as i32;
+    let half_width = 2 * config.chunk_dims().x.max(config.chunk_dims().z) as i32;
+    let height = config.sea_level.max(1) as i32;
+    let spread = |i: i32, size: i32| i * size / side;
+
+    let mut solid = 0;
+    for x in 0..side {
+        for y in 0..side {
+            for z in 0..side {
+                let pos = IVec3::new(
+                    config.spawn_center.x - half_width + spread(x, 2 * half_width),
+                    spread(y, height),
+                    config.spawn_center.z - half_width + spread(z, 2 * half_width),
+                );
+                if is_block(pos, noise, config).is_solid() {
+                    solid += 1;
+                }
+            }
+        }
+    }
+
+    solid as f64 / (side * side * side) as f64
+}
+
+/// Returns where the camera starts: [`SPAWN_CLEARANCE`] blocks above the ground in the column of the spawn center, so
+/// it never starts inside the terrain. Water counts as ground, so it doesn't start under the sea either.
+///
+/// The heightmap is sampled straight away, the other modes can have overhangs, so their column is scanned down from
+/// the top. A column without any ground keeps the height of the spawn center.
+pub fn spawn_position(noise: &TerrainNoise, config: &GenerationConfig) -> IVec3 {
+    let center = config.spawn_center;
+    let ground = if config.terrain_mode == TerrainMode::Heightmap && !config.preview {
+        let height = surface_height(center, noise, config) as i32;
+        Some(height.max(config.sea_level as i32))
+    } else {
+        (0..config.chunk_dims().y as i32)
+            .rev()
+            .find(|&y| is_block(IVec3::new(center.x, y, center.z), noise, config) != BlockType::Air)
+    };
+
+    match ground {
+        Some(ground) => IVec3::new(center.x, ground + 1 + SPAWN_CLEARANCE, center.z),
+        None => center,
+    }
+}
+
+/// Returns the block at the world position, sampled straight from the noise.
+pub fn is_block(pos: IVec3, noise: &TerrainNoise, config: &GenerationConfig) -> BlockType {
+    // is blocks
+
+    // limit the world size because it will start breaking at extreme distances
+    if !IVec2XZ::from_block(pos, config.chunk_dims()).in_world() {
+        return BlockType::Air;
+    }
+
+    // Limit the world sky
+    if pos.y >= config.chunk_dims().y as i32 - 1 {
+        return BlockType::Air;
+    }
+
+    if let TerrainMode::Flat { ground_height } = config.terrain_mode {
+        return flat_generation(pos, ground_height);
+    }
+
+    if let TerrainMode::Sphere { center, radius } = config.terrain_mode {
+        return sphere_generation(pos, center, radius, noise, config);
+    }
+
+    if config.preview {
+        return preview_generation(pos, noise, config);
+    }
+
+    // Set bedrock
+    if pos.y < config.bedrock_layers.max(1) as i32 {
+        return BlockType::Bedrock;
+    }
+
+    if config.terrain_mode == TerrainMode::Heightmap {
+        return heightmap_generation(pos, noise, config);
+    }
+
+    if let TerrainMode::Ridged3D { frequency, .. } = config.terrain_mode {
+        return ridged_generation(pos, frequency, noise, config);
+    }
+
+    // // Tests
+    // if pos.y == 0 {
+    //     return BlockType::Bedrock;
+    // } else {
+    //     let surface_block = surface_generation(pos, perlin);
+    //     surface_block
+    // }
+
+    // Generate the 2d surface block. If it's a block, check if a cave should be generated.
+    // Lava on air blocks below
+    let surface_block = surface_generation(pos, noise, config);
+    if surface_block != BlockType::Air {
+        let cave_block = cave_generation(pos, noise, config);
+        if cave_block == BlockType::Air {
+            if pos.y <= LAVA_HEIGHT as i32 {
+                BlockType::Lava
+            } else {
+                // TODO: Don't spawn caves between BLEND_HEIGHT and below WATER_HEIGHT
+                cave_block
+            }
+        } else {
+            surface_block
+        }
+    } else {
+        surface_block
+    }
+
+}
+
+/// The function that is used to interpolate between the noise values.
+///
+/// This function is used to make caves and land coexist. It's a smooth linear line from 0 to 256.
+/// TODO: Implement this into is_block in a way that makes sense.
+// fn noise_interpolation(y: i32) -> i32 {
+//     // Linear interpolation
+//     (y as f32 * 256.0 / CHUNK_HEIGHT as f32) as i32
+// }
+
+/// Samples fractal Brownian motion noise at the point.
+///
+/// Adds `octaves` layers of Perlin noise, each one with `lacunarity` times the frequency and `persistence` times
+/// the amplitude of the previous one. The result is normalized back to roughly (-1, 1), so with a single
+/// octave this is exactly `perlin.get(point)`.
+pub fn fbm<const N: usize>(
+    perlin: &Perlin,
+    point: [f64; N],
+    octaves: u32,
+    lacunarity: f64,
+    persistence: f64,
+) -> f64
+where
+    Perlin: NoiseFn<f64, N>,
+{
+    let mut value = 0.;
+    let mut total_amplitude = 0.;
+    let mut frequency = 1.;
+    let mut amplitude = 1.;
+
+    for _ in 0..octaves.max(1) {
+        value += perlin.get(point.map(|p| p * frequency)) * amplitude;
+        total_amplitude += amplitude;
+
+        frequency *= lacunarity;
+        amplitude *= persistence;
+    }
+
+    value / total_amplitude
+}
+
+/// Eases a value between 0 and 1 in and out, clamping it to that range first.
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+/// Linearly interpolates from `a` to `b`.
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Remaps a value from one range to another.
+fn remap(value: f32, from_min: f32, from_max: f32, to_min: f32, to_max: f32) -> f32 {
+    (value - from_min) / (from_max - from_min) * (to_max - to_min) + to_min
+}
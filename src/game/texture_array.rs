@@ -0,0 +1,278 @@
+use bevy::{
+    asset::LoadState,
+    pbr::{MaterialPipeline, MaterialPipelineKey, MeshPipelineKey},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    render::{
+        mesh::{MeshVertexAttribute, MeshVertexBufferLayout},
+        render_resource::{
+            AddressMode, AsBindGroup, Extent3d, RenderPipelineDescriptor, SamplerDescriptor,
+            ShaderRef, SpecializedMeshPipelineError, TextureDimension, TextureFormat,
+            TextureViewDescriptor, TextureViewDimension, VertexFormat,
+        },
+        texture::{ImageSampler, TextureFormatPixelInfo},
+    },
+};
+
+use super::common::*;
+
+/// The layer of the [`ChunkTextures::array`] a face is textured with, which is its tile in the atlas.
+pub const ATTRIBUTE_TEXTURE_LAYER: MeshVertexAttribute =
+    MeshVertexAttribute::new("TextureLayer", 2_137_000_001, VertexFormat::Uint32);
+
+/// Draws the greedy meshes. Their merged quads have UVs bigger than 1, which repeat the texture once per block. That
+/// can't be done inside a tile of the atlas, so every tile is a layer of a texture array instead.
+#[derive(AsBindGroup, TypeUuid, TypePath, Clone)]
+#[uuid = "6f3c8a52-8d4e-4b1a-9c27-5a0e2f1d7b93"]
+#[bind_group_data(ChunkArrayMaterialKey)]
+pub struct ChunkArrayMaterial {
+    #[texture(0, dimension = "2d_array")]
+    #[sampler(1)]
+    pub array_texture: Handle<Image>,
+    /// Draws the back faces too, like the `cull_mode: None` of a `StandardMaterial`.
+    pub double_sided: bool,
+}
+
+/// What the pipeline of a [`ChunkArrayMaterial`] is specialized for.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ChunkArrayMaterialKey {
+    double_sided: bool,
+}
+
+impl From<&ChunkArrayMaterial> for ChunkArrayMaterialKey {
+    fn from(material: &ChunkArrayMaterial) -> Self {
+        Self {
+            double_sided: material.double_sided,
+        }
+    }
+}
+
+impl Material for ChunkArrayMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/chunk_array.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/chunk_array.wgsl".into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if key.bind_group_data.double_sided {
+            descriptor.primitive.cull_mode = None;
+        }
+
+        // The shadows use the default prepass shader, which doesn't know about the texture layer.
+        if key.mesh_key.contains(MeshPipelineKey::DEPTH_PREPASS) {
+            return Ok(());
+        }
+
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(3),
+            ATTRIBUTE_TEXTURE_LAYER.at_shader_location(4),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+
+        Ok(())
+    }
+}
+
+/// The texture array made from the block atlas, and the material drawing with it.
+///
+/// The array is a blank placeholder until the atlas is loaded, see [`build_texture_array`].
+#[derive(Resource)]
+pub struct ChunkTextures {
+    pub array: Handle<Image>,
+    pub material: Handle<ChunkArrayMaterial>,
+}
+
+pub fn setup_texture_array(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ChunkArrayMaterial>>,
+) {
+    // One white layer, so the material can be bound before the atlas is loaded.
+    let mut placeholder = Image::new_fill(
+        Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[255; 4],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    placeholder.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..default()
+    });
+
+    let array = images.add(placeholder);
+    let material = materials.add(ChunkArrayMaterial {
+        array_texture: array.clone(),
+        double_sided: false,
+    });
+    commands.insert_resource(ChunkTextures { array, material });
+}
+
+/// Replaces the placeholder of the [`ChunkTextures`] with the atlas as a texture array once the atlas is loaded.
+pub fn build_texture_array(
+    mut done: Local<bool>,
+    game_atlas: Res<GameTextureAtlas>,
+    chunk_textures: Res<ChunkTextures>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ChunkArrayMaterial>>,
+) {
+    if *done {
+        return;
+    }
+    let Some(atlas) = images.get(&game_atlas.0.texture) else {
+        return;
+    };
+
+    let array = atlas_to_array(atlas, ATLAS_TILES);
+    if let Some(image) = images.get_mut(&chunk_textures.array) {
+        *image = array;
+    }
+    // The material only picks up the new texture when it changes itself.
+    materials.get_mut(&chunk_textures.material);
+    *done = true;
+}
+
+/// Replaces the atlas with a [`fallback_atlas`] if blocks.png fails to load, so the chunks are still shaded instead
+/// of showing up untextured.
+pub fn atlas_fallback(
+    mut done: Local<bool>,
+    asset_server: Res<AssetServer>,
+    game_atlas: Res<GameTextureAtlas>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if *done || asset_server.get_load_state(&game_atlas.0.texture) != LoadState::Failed {
+        return;
+    }
+
+    warn!("Failed to load the block textures, using a solid color for every tile instead");
+    images.set_untracked(&game_atlas.0.texture, fallback_atlas(ATLAS_TILES, ATLAS_TILE_SIZE));
+    *done = true;
+}
+
+/// Creates a square atlas of `tiles` by `tiles` tiles of `tile_size` pixels, each filled with its own color. The
+/// block types keep looking different from each other without any textures.
+pub fn fallback_atlas(tiles: u32, tile_size: u32) -> Image {
+    let size = tiles * tile_size;
+
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let tile = x / tile_size + y / tile_size * tiles;
+            // Stepping the hue by the golden angle keeps the colors of neighbouring tiles far apart.
+            let color = Color::hsl(tile as f32 * 137.5 % 360., 0.5, 0.5);
+            data.extend_from_slice(&color.as_rgba_u8());
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Creates the [`EmissiveAtlas`] from the [`BlockInfo::emissive`] of every block.
+pub fn setup_emissive_atlas(
+    mut commands: Commands,
+    block_registry: Res<BlockRegistry>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let atlas = emissive_atlas(&block_registry, ATLAS_TILES, ATLAS_TILE_SIZE);
+    commands.insert_resource(EmissiveAtlas(images.add(atlas)));
+}
+
+/// Creates a square atlas of `tiles` by `tiles` tiles of `tile_size` pixels, where every tile of a glowing block is
+/// filled with its light and the rest is black.
+pub fn emissive_atlas(registry: &BlockRegistry, tiles: u32, tile_size: u32) -> Image {
+    let size = tiles * tile_size;
+
+    let mut tile_colors = vec![Color::BLACK; (tiles * tiles) as usize];
+    for block in BlockType::ALL {
+        let info = registry.get(block);
+        for face in [BlockFace::Top, BlockFace::Bottom, BlockFace::Front] {
+            if let Some(color) = tile_colors.get_mut(info.textures.face(face) as usize) {
+                if info.emissive != Color::BLACK {
+                    *color = info.emissive;
+                }
+            }
+        }
+    }
+
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let tile = x / tile_size + y / tile_size * tiles;
+            data.extend_from_slice(&tile_colors[tile as usize].as_rgba_u8());
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Cuts a square atlas of `tiles` by `tiles` into a texture array with one layer per tile, row by row. The array
+/// repeats when sampled outside of 0 to 1.
+pub fn atlas_to_array(atlas: &Image, tiles: u32) -> Image {
+    let format = atlas.texture_descriptor.format;
+    let pixel_size = format.pixel_size();
+    let atlas_width = atlas.texture_descriptor.size.width as usize;
+    let tile_size = atlas_width / tiles as usize;
+    let layers = tiles * tiles;
+
+    // The tiles stacked on top of each other.
+    let mut data = Vec::with_capacity(tile_size * tile_size * layers as usize * pixel_size);
+    for layer in 0..layers as usize {
+        let (tile_x, tile_y) = (layer % tiles as usize, layer / tiles as usize);
+        for row in 0..tile_size {
+            let start =
+                ((tile_y * tile_size + row) * atlas_width + tile_x * tile_size) * pixel_size;
+            data.extend_from_slice(&atlas.data[start..start + tile_size * pixel_size]);
+        }
+    }
+
+    let mut array = Image::new(
+        Extent3d {
+            width: tile_size as u32,
+            height: tile_size as u32 * layers,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        format,
+    );
+    array.reinterpret_stacked_2d_as_array(layers);
+    array.sampler_descriptor = ImageSampler::Descriptor(SamplerDescriptor {
+        address_mode_u: AddressMode::Repeat,
+        address_mode_v: AddressMode::Repeat,
+        ..ImageSampler::nearest_descriptor()
+    });
+
+    array
+}
@@ -0,0 +1,71 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use super::common::*;
+use super::terrain::{is_block, SharedTerrainNoise};
+
+/// Reads and changes blocks by their world position, whichever chunk they are in.
+///
+/// The blocks of the loaded chunks are in their [`ChunkData`]. The blocks of the other chunks are sampled from the
+/// noise with the [`ModifiedChunks`] on top, the same as they will be once the chunk is generated.
+#[derive(SystemParam)]
+pub struct VoxelWorld<'w, 's> {
+    pub chunks_loaded: Res<'w, ChunksLoaded>,
+    pub chunk_data: Query<'w, 's, &'static mut ChunkData>,
+    pub modified: ResMut<'w, ModifiedChunks>,
+    pub config: Res<'w, GenerationConfig>,
+    pub noise: Res<'w, SharedTerrainNoise>,
+    pub edits: ResMut<'w, BlockEdits>,
+}
+
+impl VoxelWorld<'_, '_> {
+    /// Returns the block at the world position, or `None` if its chunk isn't loaded yet.
+    pub fn loaded_block(&self, pos: IVec3) -> Option<BlockType> {
+        let dims = self.config.chunk_dims();
+        let entity = self.chunks_loaded.chunks.get(&IVec2XZ::from_block(pos, dims))?;
+        let data = self.chunk_data.get(*entity).ok()?;
+
+        data.0.get(world_block_index(pos, dims)?).copied()
+    }
+
+    /// Returns the block at the world position. Above and below the world is air.
+    pub fn get_block(&self, pos: IVec3) -> BlockType {
+        if let Some(block) = self.loaded_block(pos) {
+            return block;
+        }
+
+        let dims = self.config.chunk_dims();
+        if world_block_index(pos, dims).is_none() {
+            return BlockType::Air;
+        }
+
+        self.modified
+            .block(pos, dims)
+            .unwrap_or_else(|| is_block(pos, &self.noise.get(&self.config), &self.config))
+    }
+
+    /// Changes the block at the world position and remembers the edit in the [`ModifiedChunks`], so it's still there
+    /// when the chunk is generated again.
+    ///
+    /// Nothing is re-meshed, that's up to the caller, but the edit is added to the [`BlockEdits`]. Does nothing above or
+    /// below the world.
+    pub fn set_block(&mut self, pos: IVec3, block: BlockType) {
+        let dims = self.config.chunk_dims();
+        // The config may have changed the chunk size since the last edits.
+        self.modified.resize(dims);
+        let Some(index) = world_block_index(pos, dims) else {
+            return;
+        };
+        let chunk_position = IVec2XZ::from_block(pos, dims);
+        let old_block = self.get_block(pos);
+
+        if let Some(&entity) = self.chunks_loaded.chunks.get(&chunk_position) {
+            if let Ok(mut data) = self.chunk_data.get_mut(entity) {
+                if let Some(data_block) = data.0.get_mut(index) {
+                    *data_block = block;
+                }
+            }
+        }
+        self.modified.record(chunk_position, index, block);
+        self.edits.0.push((pos, old_block));
+    }
+}
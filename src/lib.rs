@@ -0,0 +1,7 @@
+//! The world generation and meshing, split from the game so the benchmarks can use it without a Bevy `App`.
+
+pub mod game;
+
+// this is in tests.rs
+#[cfg(test)]
+mod tests;
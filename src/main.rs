@@ -1,10 +1,15 @@
-use std::collections::HashSet;
 use std::thread::spawn;
+use std::time::Duration;
 
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
 use bevy::diagnostic::SystemInformationDiagnosticsPlugin;
+use bevy::pbr::wireframe::WireframePlugin;
+use bevy::pbr::{CascadeShadowConfigBuilder, DirectionalLightShadowMap};
 use bevy::pbr::NotShadowCaster;
 use bevy::prelude::*;
+use bevy::render::settings::{WgpuFeatures, WgpuSettings};
+use bevy::render::RenderPlugin;
+use bevy::time::common_conditions::on_timer;
 use bevy_atmosphere::prelude::*;
 use bevy_flycam::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
@@ -12,15 +17,47 @@ use bevy_prototype_debug_lines::*;
 use bevy_rapier3d::prelude::*;
 use color_eyre::eyre::Result;
 
-mod game;
+use voxel_generation_rust::game;
 use game::chunk::chunk_system;
 use game::chunk::handle_mesh_tasks;
+use game::chunk::MeshBufferPool;
+use game::chunk::regenerate_on_config_change;
+use game::chunk::setup_block_materials;
+use game::chunk::setup_chunk_materials;
+use game::chunk::track_chunk_progress;
+use game::chunk::remesh_dirty_chunks;
+use game::chunk::update_terrain_noise;
 use game::common::*;
+use game::debug::cave_threshold_keyboard;
 use game::debug::chunk_border;
+use game::debug::chunk_double_sided;
+use game::debug::chunk_gizmos;
+use game::debug::chunk_visibility;
+use game::debug::chunk_wireframe;
 use game::debug::debug_keyboard;
+use game::debug::meshing_keyboard;
+use game::debug::msaa_keyboard;
+use game::debug::seed_keyboard;
+use game::debug::solid_fraction_keyboard;
+use game::debug::world_render_stats;
+use game::export::export_keyboard;
 use game::hud::setup_hud;
+use game::hud::setup_noise_overlay;
+use game::hud::update_chunk_progress_text;
+use game::hud::update_noise_overlay;
 use game::hud::update_text;
-use game::camera::*;
+use game::interaction::break_block;
+use game::interaction::place_block;
+use game::interaction::select_block;
+use game::player::player_physics;
+use game::player::walk_mode_keyboard;
+use game::save::save_keyboard;
+use game::sky::update_fog;
+use game::terrain::{spawn_position, SharedTerrainNoise};
+use game::texture_array::{
+    atlas_fallback, build_texture_array, setup_emissive_atlas, setup_texture_array,
+    ChunkArrayMaterial,
+};
 
 fn main() -> Result<()> {
     color_eyre::install()?;
@@ -37,15 +74,22 @@ fn main() -> Result<()> {
     };
 
     App::new()
-        .insert_resource(Msaa::Sample2)
-        .insert_resource(PlayerPos{
-            pos: Vec3::new(0.0, 0.0, 0.0),
-            rot: Quat::IDENTITY,
+        .insert_resource(MSAA_CYCLE[0])
+        .insert_resource(ClearColor(SKY_COLOR))
+        .insert_resource(DirectionalLightShadowMap {
+            size: SHADOW_MAP_SIZE,
         })
         .add_plugins(
             DefaultPlugins
                 .set(ImagePlugin::default_nearest())
-                .set(window),
+                .set(window)
+                // Needed by the wireframe debug view.
+                .set(RenderPlugin {
+                    wgpu_settings: WgpuSettings {
+                        features: WgpuFeatures::POLYGON_MODE_LINE,
+                        ..default()
+                    },
+                }),
         )
         // == Plugins ==
         .add_plugins(WorldInspectorPlugin::new())
@@ -53,6 +97,8 @@ fn main() -> Result<()> {
         .add_plugins(SystemInformationDiagnosticsPlugin)
         .add_plugins(DebugLinesPlugin::with_depth_test(true))
         .add_plugins(AtmospherePlugin)
+        .add_plugins(WireframePlugin)
+        .add_plugins(MaterialPlugin::<ChunkArrayMaterial>::default())
         .add_plugins(NoCameraPlayerPlugin)
         // .add_plugins(RapierDebugRenderPlugin
         //     {
@@ -63,32 +109,93 @@ fn main() -> Result<()> {
         // )
         .insert_resource(MovementSettings {
             sensitivity: 0.00015, // default: 0.00012
-            speed: 24.0,         // default: 12.0
+            speed: FLY_SPEED,    // default: 12.0
         })
         // Rapier
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
         // .add_plugins(RapierDebugRenderPlugin::default())
         // == Resources ==
-        .insert_resource(ChunksLoaded {
-            chunks: HashSet::new(),
-        })
+        .init_resource::<ChunksLoaded>()
+        .init_resource::<RenderDistance>()
+        .register_type::<RenderDistance>()
+        .init_resource::<FogDistance>()
+        .register_type::<FogDistance>()
         .insert_resource(Generating(true))
         .insert_resource(ChunkBorderToggled(true))
-        .init_resource::<InputState>()
+        .init_resource::<WalkMode>()
+        .init_resource::<WireframeToggled>()
+        .init_resource::<DoubleSidedToggled>()
+        .init_resource::<ChunkGizmosToggled>()
+        .init_resource::<GenerationConfig>()
+        .init_resource::<BlockRegistry>()
+        .init_resource::<SelectedBlock>()
+        .init_resource::<ModifiedChunks>()
+        .init_resource::<BlockEdits>()
+        .init_resource::<MeshBufferPool>()
+        .init_resource::<SharedTerrainNoise>()
+        .register_type::<GenerationConfig>()
+        .init_resource::<ExportSettings>()
+        .register_type::<ExportSettings>()
+        .register_type::<ChunkStats>()
+        .register_type::<GenTime>()
+        .init_resource::<ChunkProgress>()
+        .register_type::<ChunkProgress>()
+        .init_resource::<WorldRenderStats>()
+        .register_type::<WorldRenderStats>()
         // == Systems ==
-        .add_systems(Startup, (setup, setup_hud,spawn_player))
+        .add_systems(
+            Startup,
+            (
+                setup_hud,
+                setup_noise_overlay,
+                setup_texture_array,
+                setup_block_materials,
+                // The chunk materials need the atlases.
+                (setup, setup_emissive_atlas, apply_deferred, setup_chunk_materials).chain(),
+            ),
+        )
+        // Before anything starts generating with the new config.
+        .add_systems(PreUpdate, update_terrain_noise)
         .add_systems(
             Update,
             (
                 chunk_border,
+                chunk_gizmos,
+                (chunk_wireframe, chunk_double_sided),
+                (chunk_visibility, world_render_stats).run_if(on_timer(Duration::from_secs(1))),
                 debug_keyboard,
-                update_text,
-                chunk_system,
+                (
+                    cave_threshold_keyboard,
+                    meshing_keyboard,
+                    seed_keyboard,
+                    msaa_keyboard,
+                    solid_fraction_keyboard,
+                ),
+                (
+                    update_text,
+                    update_noise_overlay,
+                    (track_chunk_progress, update_chunk_progress_text).chain(),
+                ),
                 handle_mesh_tasks,
-                cursor_grab_system,
-                move_player,
-                player_look,
-                update_camera
+                // The level of detail re-meshes through a new task, which must not be removed by the finished old one.
+                chunk_system.after(handle_mesh_tasks),
+                // The same goes for the tasks of a new config.
+                regenerate_on_config_change.after(handle_mesh_tasks),
+                (
+                    (break_block, place_block),
+                    // The edited chunks are marked by commands, which have to be applied before they are re-meshed.
+                    apply_deferred,
+                    // Edits re-mesh through a new task, which must not be removed by the finished old one.
+                    remesh_dirty_chunks.after(handle_mesh_tasks),
+                )
+                    .chain(),
+                select_block,
+                save_keyboard,
+                export_keyboard,
+                walk_mode_keyboard,
+                player_physics,
+                update_fog,
+                (atlas_fallback, build_texture_array).chain(),
             ),
         )
         .run();
@@ -100,24 +207,40 @@ fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut grav_scale: Query<&mut GravityScale>,
+    generation_config: Res<GenerationConfig>,
+    noise: Res<SharedTerrainNoise>,
 ) {
     // Setup texture atlas
     let texture_handle = asset_server.load("textures/blocks.png");
-    let texture_atlas =
-        TextureAtlas::from_grid(texture_handle, Vec2::new(32.0, 32.0), 7, 7, None, None); //c2 r3
+    let texture_atlas = TextureAtlas::from_grid(
+        texture_handle,
+        Vec2::splat(ATLAS_TILE_SIZE as f32),
+        ATLAS_TILES as usize,
+        ATLAS_TILES as usize,
+        None,
+        None,
+    ); //c2 r3
     commands.insert_resource(GameTextureAtlas(texture_atlas));
 
     // Sun
+    // It's high up in the sky at a fixed angle, so the tops of the blocks are the brightest and the lighting doesn't
+    // move with the camera.
     let sun_light: f32 = 0.8;
     commands.spawn(DirectionalLightBundle {
         directional_light: DirectionalLight {
             color: Color::rgb(0.98 * sun_light, 0.95 * sun_light, 0.82 * sun_light), //r0.98 g0.95 b0.82
-            shadows_enabled: false,
+            shadows_enabled: true,
             ..default()
         },
         transform: Transform::from_xyz(0.0, 0.0, 0.0)
-            .looking_at(Vec3::new(-0.15, -0.05, 0.25), Vec3::new(0.3, 1.0, 0.0)),
-
+            .looking_at(Vec3::new(-0.3, -1.0, 0.5), Vec3::Y),
+        // Most of the shadow map goes to the terrain close to the camera.
+        cascade_shadow_config: CascadeShadowConfigBuilder {
+            first_cascade_far_bound: 24.0,
+            maximum_distance: SHADOW_DISTANCE,
+            ..default()
+        }
+        .build(),
         ..default()
     });
 
@@ -126,31 +249,28 @@ fn setup(
         grav.0 = 1.0;
     }
 
+    // The chunks load around the camera, so they start around the spawn center.
+    let spawn_center =
+        spawn_position(&noise.get(&generation_config), &generation_config).as_vec3();
     commands.spawn((
         Camera3dBundle {
-            transform: Transform::from_translation(Vec3::new(0.0, 100.0, 0.0))
-                .looking_at(Vec3::new(0.0, 0.0, 0.0), Vec3::Y),
+            transform: Transform::from_translation(spawn_center)
+                .looking_at(spawn_center * Vec3::new(1.0, 0.0, 1.0), Vec3::Y),
             projection: Projection::Perspective(PerspectiveProjection {
                 fov: FOV.to_radians(),
                 ..default()
             }),
             ..default()
         },
+        // The distances are set from the `FogDistance` by `update_fog`.
         FogSettings {
-            color: Color::rgba(0.46, 0.6, 0.72, 1.0),
-            falloff: FogFalloff::Linear {
-                start: (RENDER_DISTANCE as f32 * FOG_DISTANCE) * CHUNK_SIZE as f32 * 0.8,
-                end: (RENDER_DISTANCE as f32 * FOG_DISTANCE) * CHUNK_SIZE as f32 * 0.95,
-            },
+            color: SKY_COLOR,
             ..default()
         },
         AtmosphereCamera::default(),
         NotShadowCaster,
         // RigidBody::KinematicPositionBased,
         FlyCam,
+        PlayerPhysics::default(),
     ));
 }
-
-// this is in tests.rs
-#[cfg(test)]
-mod tests;
@@ -1,3 +1,1707 @@
-// use bevy::prelude::*;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use noise::NoiseFn;
+use std::sync::Arc;
 
-// todo: create tests
+use crate::game::chunk::*;
+use crate::game::common::*;
+use crate::game::coords::*;
+use crate::game::export::*;
+use crate::game::hud::*;
+use crate::game::light::*;
+use crate::game::raycast::*;
+use crate::game::save::*;
+use crate::game::terrain::*;
+use crate::game::texture_array::*;
+
+/// The number of blocks in a chunk of the default size.
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_HEIGHT * CHUNK_SIZE;
+
+/// An empty chunk, filled with air.
+fn empty_chunk() -> Vec<BlockType> {
+    vec![BlockType::Air; CHUNK_VOLUME]
+}
+
+/// Meshes the chunk at the origin, pretending everything around it is air.
+fn mesh_surrounded_by_air(chunk_blocks: &[BlockType]) -> Mesh {
+    build_chunk_mesh(
+        chunk_blocks,
+        IVec2XZ::new(0, 0),
+        ChunkDims::default(),
+        |_| BlockType::Air,
+        &BlockRegistry::default(),
+    )
+}
+
+fn face_count(mesh: &Mesh) -> usize {
+    mesh.indices().unwrap().len() / 6
+}
+
+fn positions(mesh: &Mesh) -> &[[f32; 3]] {
+    match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(VertexAttributeValues::Float32x3(positions)) => positions,
+        _ => panic!("the mesh has no positions"),
+    }
+}
+
+fn normals(mesh: &Mesh) -> &[[f32; 3]] {
+    match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+        Some(VertexAttributeValues::Float32x3(normals)) => normals,
+        _ => panic!("the mesh has no normals"),
+    }
+}
+
+#[test]
+fn two_by_two_cube_emits_only_outer_faces() {
+    let mut chunk_blocks = empty_chunk();
+    for x in 4..6 {
+        for y in 4..6 {
+            for z in 4..6 {
+                chunk_blocks[block_index(x, y, z, ChunkDims::default())] = BlockType::Dirt;
+            }
+        }
+    }
+
+    let mesh = mesh_surrounded_by_air(&chunk_blocks);
+
+    // 6 sides of 2x2 faces each, with 4 vertices per face. None of the 12 inner faces are drawn.
+    assert_eq!(face_count(&mesh), 24);
+    assert_eq!(mesh.count_vertices(), 24 * 4);
+}
+
+/// Builds the vertex data of the chunk at the origin, pretending everything around it is air.
+fn mesh_data_surrounded_by_air(chunk_blocks: &[BlockType]) -> ChunkMeshData {
+    build_chunk_mesh_data(
+        chunk_blocks,
+        IVec2XZ::new(0, 0),
+        ChunkDims::default(),
+        |_| BlockType::Air,
+        &BlockRegistry::default(),
+    )
+}
+
+#[test]
+fn isolated_block_emits_six_faces() {
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(8, 100, 8, ChunkDims::default())] = BlockType::Dirt;
+
+    let mesh_data = mesh_data_surrounded_by_air(&chunk_blocks);
+
+    assert_eq!(mesh_data.indices.len(), 36);
+    assert_eq!(mesh_data.vertices.len(), 24);
+    assert_eq!(mesh_data.normals.len(), 24);
+    assert_eq!(mesh_data.uvs.len(), 24);
+}
+
+#[test]
+fn column_of_two_blocks_emits_ten_faces() {
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(8, 100, 8, ChunkDims::default())] = BlockType::Dirt;
+    chunk_blocks[block_index(8, 101, 8, ChunkDims::default())] = BlockType::Dirt;
+
+    let mesh_data = mesh_data_surrounded_by_air(&chunk_blocks);
+
+    // The faces where the blocks touch are hidden.
+    assert_eq!(mesh_data.indices.len(), 10 * 6);
+    assert_eq!(mesh_data.vertices.len(), 10 * 4);
+}
+
+#[test]
+fn full_chunk_emits_only_its_shell() {
+    let chunk_blocks = vec![BlockType::Stone; CHUNK_VOLUME];
+
+    let mesh_data = mesh_data_surrounded_by_air(&chunk_blocks);
+
+    // The top and the bottom, plus the four sides.
+    let shell_faces = 2 * CHUNK_SIZE * CHUNK_SIZE + 4 * CHUNK_SIZE * CHUNK_HEIGHT;
+    assert_eq!(mesh_data.indices.len(), shell_faces * 6);
+    assert_eq!(mesh_data.vertices.len(), shell_faces * 4);
+}
+
+#[test]
+fn chunks_of_other_sizes_mesh_correctly() {
+    for chunk_size in [1, 32] {
+        let registry = BlockRegistry::default();
+        let dims = ChunkDims::new(chunk_size, CHUNK_HEIGHT, chunk_size);
+        let volume = dims.volume();
+
+        // A single block in the corner.
+        let mut chunk_blocks = vec![BlockType::Air; volume];
+        chunk_blocks[block_index(0, 100, 0, dims)] = BlockType::Dirt;
+        let mesh_data = build_chunk_mesh_data(
+            &chunk_blocks,
+            IVec2XZ::new(2, -1),
+            dims,
+            |_| BlockType::Air,
+            &registry,
+        );
+        assert_eq!(mesh_data.indices.len(), 6 * 6, "chunk size {}", chunk_size);
+
+        // The corner block sits at the origin of the chunk.
+        let origin = IVec2XZ::new(2, -1).origin(dims);
+        let min = mesh_data
+            .vertices
+            .iter()
+            .fold(Vec3::MAX, |min, &vertex| min.min(Vec3::from_array(vertex)));
+        assert_eq!(min, origin.as_vec3() + Vec3::Y * 100.);
+
+        // A full chunk only has its shell.
+        let full_blocks = vec![BlockType::Stone; volume];
+        let mesh_data = build_chunk_mesh_data(
+            &full_blocks,
+            IVec2XZ::new(0, 0),
+            dims,
+            |_| BlockType::Air,
+            &registry,
+        );
+        let shell_faces = 2 * chunk_size * chunk_size + 4 * chunk_size * CHUNK_HEIGHT;
+        assert_eq!(mesh_data.indices.len(), shell_faces * 6, "chunk size {}", chunk_size);
+    }
+}
+
+#[test]
+fn chunk_vertices_are_in_world_space() {
+    // The chunk one step along +X, with a block in its corner.
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(0, 100, 0, ChunkDims::default())] = BlockType::Dirt;
+    let mesh_data = build_chunk_mesh_data(
+        &chunk_blocks,
+        IVec2XZ::new(1, 0),
+        ChunkDims::default(),
+        |_| BlockType::Air,
+        &BlockRegistry::default(),
+    );
+
+    // The chunk entity stays at the origin, so the block must already be where it is in the world.
+    let (min, max) = mesh_data
+        .vertices
+        .iter()
+        .map(|&vertex| Vec3::from_array(vertex))
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), vertex| {
+            (min.min(vertex), max.max(vertex))
+        });
+    let expected = Vec3::new(CHUNK_SIZE as f32, 100., 0.);
+    assert_eq!(min, expected);
+    assert_eq!(max, expected + Vec3::ONE);
+}
+
+#[test]
+fn tall_chunks_mesh_correctly() {
+    let registry = BlockRegistry::default();
+    let dims = ChunkDims::new(4, 16, 4);
+
+    // A block in the top corner, touching three sides of the chunk.
+    let mut chunk_blocks = vec![BlockType::Air; dims.volume()];
+    chunk_blocks[block_index(3, 15, 3, dims)] = BlockType::Dirt;
+    let mesh_data = build_chunk_mesh_data(
+        &chunk_blocks,
+        IVec2XZ::new(-1, 1),
+        dims,
+        |_| BlockType::Air,
+        &registry,
+    );
+    assert_eq!(mesh_data.indices.len(), 6 * 6);
+
+    let max = mesh_data
+        .vertices
+        .iter()
+        .fold(Vec3::MIN, |max, &vertex| max.max(Vec3::from_array(vertex)));
+    assert_eq!(max, Vec3::new(0., 16., 8.));
+
+    // A full chunk only has its shell, with each axis bounded separately.
+    let full_blocks = vec![BlockType::Stone; dims.volume()];
+    let mesh_data = build_chunk_mesh_data(
+        &full_blocks,
+        IVec2XZ::new(0, 0),
+        dims,
+        |_| BlockType::Air,
+        &registry,
+    );
+    let shell_faces = 2 * dims.x * dims.z + 2 * dims.x * dims.y + 2 * dims.z * dims.y;
+    assert_eq!(mesh_data.indices.len(), shell_faces * 6);
+
+    // The index round trips through the non-cubic layout.
+    for index in [0, 5, 63, dims.volume() - 1] {
+        let (x, y, z) = block_position(index, dims);
+        assert_eq!(block_index(x, y, z, dims), index);
+    }
+}
+
+#[test]
+fn faces_are_counter_clockwise_from_outside() {
+    for (face, outward) in [
+        (BlockFace::Top, Vec3::Y),
+        (BlockFace::Bottom, Vec3::NEG_Y),
+        (BlockFace::Right, Vec3::X),
+        (BlockFace::Left, Vec3::NEG_X),
+        (BlockFace::Front, Vec3::Z),
+        (BlockFace::Back, Vec3::NEG_Z),
+    ] {
+        let (mut vertices, mut indices, mut normals, mut uvs) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        push_face(
+            &mut vertices,
+            &mut indices,
+            &mut normals,
+            &mut uvs,
+            Vec3::ZERO,
+            face,
+            BlockType::Dirt,
+            &BlockRegistry::default(),
+        );
+
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from_array(vertices[triangle[i] as usize]));
+            let winding = (b - a).cross(c - a).normalize();
+            assert_eq!(winding, outward, "{:?} face is wound the wrong way", face);
+        }
+        for normal in normals {
+            assert_eq!(Vec3::from_array(normal), outward, "{:?} face has the wrong normal", face);
+        }
+    }
+}
+
+#[test]
+fn full_chunks_have_no_faces_between_them() {
+    let chunk_blocks = vec![BlockType::Stone; CHUNK_VOLUME];
+
+    // Every chunk around this one is full too.
+    let mesh = build_chunk_mesh(
+        &chunk_blocks,
+        IVec2XZ::new(1, 0),
+        ChunkDims::default(),
+        |_| BlockType::Stone,
+        &BlockRegistry::default(),
+    );
+
+    // Only the top and the bottom of the world are left.
+    assert_eq!(face_count(&mesh), 2 * CHUNK_SIZE * CHUNK_SIZE);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_generation_matches_serial() {
+    let config = GenerationConfig::default();
+    let noise = TerrainNoise::new(&config);
+
+    for chunk_position in [IVec2XZ::new(0, 0), IVec2XZ::new(-3, 7)] {
+        let serial = generate_chunk_blocks(chunk_position, &noise, &config);
+        let parallel = generate_chunk_blocks_parallel(chunk_position, &noise, &config);
+        assert!(serial == parallel);
+    }
+}
+
+#[test]
+fn raycast_along_x_hits_the_left_face() {
+    let hit = raycast_voxel(
+        Vec3::new(-5.5, 0.5, 0.5),
+        Vec3::X,
+        10.,
+        |block| block == IVec3::ZERO,
+    );
+    assert_eq!(hit, Some((IVec3::ZERO, BlockFace::Left)));
+
+    // The block is too far away.
+    let miss = raycast_voxel(
+        Vec3::new(-5.5, 0.5, 0.5),
+        Vec3::X,
+        4.,
+        |block| block == IVec3::ZERO,
+    );
+    assert_eq!(miss, None);
+}
+
+#[test]
+fn rle_round_trips() {
+    use BlockType::*;
+
+    let blocks = [Stone, Stone, Stone, Dirt, Grass, Air, Air];
+    let runs = rle_encode(&blocks);
+    assert!(runs == [(Stone, 3), (Dirt, 1), (Grass, 1), (Air, 2)]);
+    assert!(rle_decode(&runs) == blocks);
+
+    assert!(rle_encode(&[]).is_empty());
+    assert!(rle_decode(&[]).is_empty());
+}
+
+#[test]
+fn rle_round_trips_a_generated_chunk() {
+    let config = GenerationConfig::default();
+    let noise = TerrainNoise::new(&config);
+    let chunk_blocks = generate_chunk_blocks(IVec2XZ::new(2, -1), &noise, &config);
+
+    let runs = rle_encode(&chunk_blocks);
+    assert!(rle_decode(&runs) == chunk_blocks);
+    // Terrain is mostly long runs of air and stone.
+    assert!(runs.len() < chunk_blocks.len() / 2);
+}
+
+/// Adds up the area of the triangles in the mesh, for each normal.
+fn area_by_normal(mesh: &Mesh) -> Vec<([i32; 3], f32)> {
+    let positions = positions(mesh);
+
+    let mut areas: Vec<([i32; 3], f32)> = Vec::new();
+    for triangle in mesh.indices().unwrap().iter().collect::<Vec<_>>().chunks(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vec3::from_array(positions[triangle[i]]));
+        let cross = (b - a).cross(c - a);
+        let normal = cross.normalize().round().as_ivec3().to_array();
+
+        match areas.iter_mut().find(|(n, _)| *n == normal) {
+            Some((_, area)) => *area += cross.length() / 2.,
+            None => areas.push((normal, cross.length() / 2.)),
+        }
+    }
+
+    areas.sort_by_key(|(normal, _)| *normal);
+    areas
+}
+
+#[test]
+fn greedy_mesh_covers_the_same_area_as_naive() {
+    let config = GenerationConfig::default();
+    let noise = TerrainNoise::new(&config);
+    let registry = BlockRegistry::default();
+    let chunk_blocks = generate_chunk_blocks(IVec2XZ::new(0, 0), &noise, &config);
+
+    let position = IVec2XZ::new(0, 0);
+    let air = |_: IVec3| BlockType::Air;
+    let naive = build_chunk_mesh(&chunk_blocks, position, ChunkDims::default(), air, &registry);
+    let greedy = greedy_mesh(&chunk_blocks, position, ChunkDims::default(), air, &registry);
+
+    let (naive_areas, greedy_areas) = (area_by_normal(&naive), area_by_normal(&greedy));
+    assert_eq!(naive_areas.len(), greedy_areas.len());
+    for ((naive_normal, naive_area), (greedy_normal, greedy_area)) in
+        naive_areas.into_iter().zip(greedy_areas)
+    {
+        assert_eq!(naive_normal, greedy_normal);
+        assert!(
+            (naive_area - greedy_area).abs() < 0.01,
+            "{:?}: naive {} vs greedy {}",
+            naive_normal,
+            naive_area,
+            greedy_area
+        );
+    }
+
+    assert!(face_count(&greedy) < face_count(&naive));
+}
+
+#[test]
+fn merged_quad_uvs_tile_once_per_block() {
+    let registry = BlockRegistry::default();
+    let mut chunk_blocks = empty_chunk();
+    for x in 4..7 {
+        chunk_blocks[block_index(x, 100, 8, ChunkDims::default())] = BlockType::Stone;
+    }
+
+    let mesh = greedy_mesh(
+        &chunk_blocks,
+        IVec2XZ::new(0, 0),
+        ChunkDims::default(),
+        |_| BlockType::Air,
+        &registry,
+    );
+
+    let normals = normals(&mesh);
+    let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) else {
+        panic!("no uvs");
+    };
+    let Some(VertexAttributeValues::Uint32(layers)) = mesh.attribute(ATTRIBUTE_TEXTURE_LAYER)
+    else {
+        panic!("no texture layers");
+    };
+
+    // The 3x1 top is a single quad, its texture repeats 3 times along the row.
+    let top: Vec<usize> = (0..normals.len()).filter(|&i| normals[i] == [0., 1., 0.]).collect();
+    assert_eq!(top.len(), 4);
+    let max_uv = top.iter().fold(Vec2::ZERO, |max, &i| max.max(Vec2::from(uvs[i])));
+    let mut spans = [max_uv.x, max_uv.y];
+    spans.sort_by(f32::total_cmp);
+    assert_eq!(spans, [1., 3.]);
+
+    let stone_top = registry.get(BlockType::Stone).textures.face(BlockFace::Top);
+    assert!(top.iter().all(|&i| layers[i] == stone_top));
+}
+
+#[test]
+fn atlas_tiles_become_array_layers() {
+    // A 2x2 atlas of single pixel tiles, each with its own red value.
+    let atlas = Image::new(
+        Extent3d {
+            width: 2,
+            height: 2,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        vec![0, 0, 0, 255, 1, 0, 0, 255, 2, 0, 0, 255, 3, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+
+    let array = atlas_to_array(&atlas, 2);
+    assert_eq!(array.texture_descriptor.size.depth_or_array_layers, 4);
+    let reds: Vec<u8> = array.data.chunks_exact(4).map(|pixel| pixel[0]).collect();
+    assert_eq!(reds, [0, 1, 2, 3]);
+}
+
+#[test]
+fn inside_corners_are_darker_than_flat_ground() {
+    let mut chunk_blocks = empty_chunk();
+    for x in 0..8 {
+        for z in 0..8 {
+            chunk_blocks[block_index(x, 10, z, ChunkDims::default())] = BlockType::Stone;
+        }
+    }
+    // A wall block standing on the floor.
+    chunk_blocks[block_index(4, 11, 4, ChunkDims::default())] = BlockType::Stone;
+
+    let mesh = mesh_surrounded_by_air(&chunk_blocks);
+    let positions = positions(&mesh);
+    let Some(bevy::render::mesh::VertexAttributeValues::Float32x4(colors)) =
+        mesh.attribute(Mesh::ATTRIBUTE_COLOR)
+    else {
+        panic!("mesh has no colors");
+    };
+    let normals = normals(&mesh);
+
+    // The brightness of the floor corners at the position.
+    let floor_brightness = |corner: [f32; 3]| -> Vec<f32> {
+        (0..positions.len())
+            .filter(|&i| positions[i] == corner && normals[i] == [0., 1., 0.])
+            .map(|i| colors[i][0])
+            .collect()
+    };
+
+    let inside_corner = floor_brightness([4., 11., 4.]);
+    let flat = floor_brightness([1., 11., 1.]);
+    assert!(!inside_corner.is_empty() && !flat.is_empty());
+    assert!(flat.iter().all(|&brightness| brightness == 1.));
+    assert!(inside_corner.iter().all(|&brightness| brightness < 1.));
+}
+
+/// The raw bytes of the blocks, so two chunks can be compared bit for bit.
+fn block_bytes(chunk_blocks: &[BlockType]) -> Vec<u8> {
+    chunk_blocks.iter().map(|&block| block as u8).collect()
+}
+
+#[test]
+fn same_seed_generates_identical_chunks() {
+    let config = GenerationConfig::default();
+    let chunk_position = IVec2XZ::new(-4, 9);
+
+    // Separately created noise with the same seed gives the same chunk.
+    let first = generate_chunk_blocks(chunk_position, &TerrainNoise::new(&config), &config);
+    let second = generate_chunk_blocks(chunk_position, &TerrainNoise::new(&config), &config);
+    assert_eq!(block_bytes(&first), block_bytes(&second));
+
+    // So does noise that already generated other chunks, in whatever order they were loaded.
+    let noise = TerrainNoise::new(&config);
+    for other_position in [IVec2XZ::new(5, 5), IVec2XZ::new(-4, 8), IVec2XZ::new(0, 0)] {
+        generate_chunk_blocks(other_position, &noise, &config);
+    }
+    let third = generate_chunk_blocks(chunk_position, &noise, &config);
+    assert_eq!(block_bytes(&first), block_bytes(&third));
+}
+
+#[test]
+fn surface_noises_are_deterministic_and_differ() {
+    let chunk_position = IVec2XZ::new(3, -2);
+    let generate = |surface_noise: SurfaceNoise| {
+        let config = GenerationConfig {
+            surface_noise,
+            ..default()
+        };
+        let first = generate_chunk_blocks(chunk_position, &TerrainNoise::new(&config), &config);
+        let second = generate_chunk_blocks(chunk_position, &TerrainNoise::new(&config), &config);
+        assert_eq!(block_bytes(&first), block_bytes(&second));
+        block_bytes(&first)
+    };
+
+    let classic = generate(SurfaceNoise::Classic);
+    let perlin = generate(SurfaceNoise::Perlin);
+    let fbm = generate(SurfaceNoise::Fbm);
+    let ridged = generate(SurfaceNoise::Ridged);
+    assert_ne!(classic, perlin);
+    assert_ne!(perlin, ridged);
+    assert_ne!(fbm, ridged);
+}
+
+#[test]
+fn classic_surface_noise_matches_the_hand_rolled_octaves() {
+    let config = GenerationConfig::default();
+    let terrain_noise = TerrainNoise::new(&config);
+    let perlin = noise::Perlin::new(config.seed);
+
+    for point in [[0.0, 0.0], [0.31, -2.7], [15.5, 4.25]] {
+        // The octaves the surface used to be summed from, at 2, 4 and 6 times the surface scale.
+        let hand_rolled: f64 = [2., 4., 6.]
+            .map(|frequency| perlin.get(point.map(|p| p * frequency)))
+            .iter()
+            .sum();
+        let classic = terrain_noise.height.get(point.map(|p| p * 2.));
+        assert!((classic * 3. - hand_rolled).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn preview_generates_one_block_per_column() {
+    let config = GenerationConfig {
+        preview: true,
+        ..default()
+    };
+    let dims = config.chunk_dims();
+    let noise = TerrainNoise::new(&config);
+    let chunk_blocks = generate_chunk_blocks(IVec2XZ::new(2, 7), &noise, &config);
+
+    for x in 0..dims.x {
+        for z in 0..dims.z {
+            let solid = (0..dims.y)
+                .filter(|&y| chunk_blocks[block_index(x, y, z, dims)] != BlockType::Air)
+                .count();
+            assert_eq!(solid, 1, "column {} {}", x, z);
+        }
+    }
+}
+
+#[test]
+fn different_seeds_generate_different_chunks() {
+    let config = GenerationConfig::default();
+    let chunk_position = IVec2XZ::new(-4, 9);
+    let first = generate_chunk_blocks(chunk_position, &TerrainNoise::new(&config), &config);
+
+    let other_seed = GenerationConfig {
+        seed: config.seed + 1,
+        ..config
+    };
+    let second = generate_chunk_blocks(chunk_position, &TerrainNoise::new(&other_seed), &other_seed);
+    assert_ne!(block_bytes(&first), block_bytes(&second));
+}
+
+#[test]
+fn full_chunk_aabb_spans_the_chunk() {
+    let dims = ChunkDims::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE);
+    let chunk_blocks = vec![BlockType::Stone; dims.volume()];
+    let position = IVec2XZ::new(1, -1);
+
+    let mesh = build_chunk_mesh(
+        &chunk_blocks,
+        position,
+        dims,
+        |_| BlockType::Air,
+        &BlockRegistry::default(),
+    );
+    let aabb = mesh.compute_aabb().unwrap();
+
+    // The bounds are in world space like the vertices, around the chunk's own blocks.
+    let size = Vec3::splat(CHUNK_SIZE as f32);
+    assert_eq!(Vec3::from(aabb.half_extents) * 2., size);
+    assert_eq!(Vec3::from(aabb.center), position.origin(dims).as_vec3() + size / 2.);
+}
+
+#[test]
+fn lod_mesh_of_a_full_chunk_covers_the_same_area() {
+    let registry = BlockRegistry::default();
+    let chunk_blocks = vec![BlockType::Stone; CHUNK_VOLUME];
+    let position = IVec2XZ::new(-2, 3);
+
+    let full = build_chunk_mesh(
+        &chunk_blocks,
+        position,
+        ChunkDims::default(),
+        |_| BlockType::Air,
+        &registry,
+    );
+    for lod in [2, 4] {
+        let (coarse, _) = build_lod_mesh(
+            &chunk_blocks,
+            position,
+            ChunkDims::default(),
+            lod,
+            MeshingMode::Naive,
+            &registry,
+            0,
+        );
+
+        // Fewer, bigger faces in the same place.
+        assert_eq!(face_count(&coarse), face_count(&full) / (lod * lod));
+        assert_eq!(area_by_normal(&coarse), area_by_normal(&full), "lod {}", lod);
+    }
+}
+
+#[test]
+fn one_block_makes_its_lod_group_solid() {
+    let dims = ChunkDims::default();
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(3, 101, 3, dims)] = BlockType::Dirt;
+    chunk_blocks[block_index(3, 100, 3, dims)] = BlockType::Stone;
+
+    let (lod_blocks, lod_dims) = downsample_blocks(&chunk_blocks, dims, 2);
+
+    assert_eq!(lod_dims, ChunkDims::new(8, 128, 8));
+    let solid: Vec<usize> = (0..lod_dims.volume())
+        .filter(|&index| lod_blocks[index] != BlockType::Air)
+        .collect();
+    assert_eq!(solid, vec![block_index(1, 50, 1, lod_dims)]);
+    // The top block of the group wins.
+    assert!(lod_blocks[block_index(1, 50, 1, lod_dims)] == BlockType::Dirt);
+}
+
+#[test]
+fn lod_groups_prefer_solid_blocks_over_water() {
+    let dims = ChunkDims::default();
+    let mut chunk_blocks = empty_chunk();
+    // Water on top of sand, like along a shore.
+    chunk_blocks[block_index(3, 101, 3, dims)] = BlockType::Water;
+    chunk_blocks[block_index(3, 100, 3, dims)] = BlockType::Sand;
+    // Only water.
+    chunk_blocks[block_index(7, 101, 7, dims)] = BlockType::Water;
+
+    let (lod_blocks, lod_dims) = downsample_blocks(&chunk_blocks, dims, 2);
+
+    assert!(lod_blocks[block_index(1, 50, 1, lod_dims)] == BlockType::Sand);
+    assert!(lod_blocks[block_index(3, 50, 3, lod_dims)] == BlockType::Water);
+}
+
+#[test]
+fn water_pool_only_emits_faces_towards_air() {
+    let registry = BlockRegistry::default();
+    let dims = ChunkDims::default();
+
+    // A 4x4x4 pool of water on a stone floor.
+    let mut chunk_blocks = empty_chunk();
+    for x in 4..8 {
+        for z in 4..8 {
+            chunk_blocks[block_index(x, 99, z, dims)] = BlockType::Stone;
+            for y in 100..104 {
+                chunk_blocks[block_index(x, y, z, dims)] = BlockType::Water;
+            }
+        }
+    }
+
+    let water = build_water_mesh_data(
+        &chunk_blocks,
+        IVec2XZ::new(0, 0),
+        dims,
+        |_| BlockType::Air,
+        &registry,
+    );
+
+    // 16 faces on top and 4 walls of 4x4 faces, none between the water blocks or against the floor.
+    let top_faces = water.normals.iter().filter(|&&normal| normal == [0., 1., 0.]).count() / 4;
+    assert_eq!(top_faces, 16);
+    assert_eq!(water.indices.len() / 6, 16 + 4 * 16);
+
+    // The floor still draws its top under the water, in the solid mesh, along with its bottom and its edges.
+    let solid = mesh_data_surrounded_by_air(&chunk_blocks);
+    assert_eq!(solid.indices.len() / 6, 16 + 16 + 4 * 4);
+}
+
+#[test]
+fn negative_world_positions_round_down_to_their_chunk() {
+    let dims = ChunkDims::default();
+    let size = CHUNK_SIZE as i32;
+
+    for (world_x, chunk_x, local_x) in [
+        (0, 0, 0),
+        (size - 1, 0, size - 1),
+        (size, 1, 0),
+        (-1, -1, size - 1),
+        (-size, -1, 0),
+        (-size - 1, -2, size - 1),
+    ] {
+        let pos = IVec3::new(world_x, 70, -world_x);
+        assert_eq!(world_to_chunk(pos, dims), IVec2XZ::new(chunk_x, (-world_x).div_euclid(size)));
+        assert_eq!(world_to_local(pos, dims).x, local_x, "world x {}", world_x);
+        assert_eq!(world_to_local(pos, dims).y, 70);
+    }
+}
+
+#[test]
+fn coordinate_conversions_round_trip() {
+    for dims in [ChunkDims::default(), ChunkDims::new(4, 16, 7)] {
+        for x in -40..40 {
+            for z in [-33, -8, -1, 0, 5, 31] {
+                let pos = IVec3::new(x, 3, z);
+                let chunk = world_to_chunk(pos, dims);
+                let local = world_to_local(pos, dims);
+
+                assert!((0..dims.x as i32).contains(&local.x));
+                assert!((0..dims.z as i32).contains(&local.z));
+                assert_eq!(chunk_local_to_world(chunk, local, dims), pos);
+            }
+        }
+    }
+}
+
+#[test]
+fn chunks_on_both_sides_of_zero_tile_seamlessly() {
+    let config = GenerationConfig::default();
+    let noise = TerrainNoise::new(&config);
+    let dims = config.chunk_dims();
+    let last = dims.x - 1;
+
+    let negative = generate_chunk_blocks(IVec2XZ::new(-1, 0), &noise, &config);
+    let positive = generate_chunk_blocks(IVec2XZ::new(0, 0), &noise, &config);
+
+    // The blocks on both sides of the seam are the ones the noise has at world X -1 and 0.
+    let mut differences = 0;
+    for y in 0..dims.y {
+        for z in 0..dims.z {
+            let west = negative[block_index(last, y, z, dims)];
+            let east = positive[block_index(0, y, z, dims)];
+            assert!(west == is_block(IVec3::new(-1, y as i32, z as i32), &noise, &config));
+            assert!(east == is_block(IVec3::new(0, y as i32, z as i32), &noise, &config));
+
+            if west != east {
+                differences += 1;
+            }
+        }
+    }
+
+    // Neighboring columns are almost the same. A shifted or mirrored chunk would differ a lot more.
+    assert!(differences < dims.y * dims.z / 20, "{} blocks differ", differences);
+}
+
+#[test]
+fn small_chunks_use_16_bit_indices() {
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(8, 100, 8, ChunkDims::default())] = BlockType::Dirt;
+
+    let mesh = mesh_surrounded_by_air(&chunk_blocks);
+    assert!(matches!(mesh.indices(), Some(Indices::U16(_))));
+
+    let (_, triangles) = get_verts_indices(&mesh);
+    assert_eq!(triangles.len(), 12);
+}
+
+#[test]
+fn single_block_exports_to_obj() {
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(8, 100, 8, ChunkDims::default())] = BlockType::Dirt;
+    let mesh = mesh_surrounded_by_air(&chunk_blocks);
+
+    let path = std::env::temp_dir().join("voxel_single_block.obj");
+    export_mesh_obj(&mesh, &path).unwrap();
+    let obj = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let count = |prefix: &str| obj.lines().filter(|line| line.starts_with(prefix)).count();
+    assert_eq!(count("v "), 24);
+    assert_eq!(count("vt "), 24);
+    assert_eq!(count("vn "), 24);
+    assert_eq!(count("f "), 12);
+    assert!(obj.contains("f 1/1/1 "));
+}
+
+#[test]
+fn huge_chunks_fall_back_to_32_bit_indices() {
+    // A checkerboard hides no faces at all, which is more vertices than a u16 can count.
+    let dims = ChunkDims::default();
+    let mut chunk_blocks = empty_chunk();
+    for index in 0..CHUNK_VOLUME {
+        let (x, y, z) = block_position(index, dims);
+        if (x + y + z) % 2 == 0 {
+            chunk_blocks[index] = BlockType::Stone;
+        }
+    }
+
+    let mesh = mesh_surrounded_by_air(&chunk_blocks);
+    assert!(mesh.count_vertices() > u16::MAX as usize);
+    assert!(matches!(mesh.indices(), Some(Indices::U32(_))));
+
+    // Right at the limit.
+    assert!(matches!(compact_indices(vec![0], u16::MAX as usize), Indices::U16(_)));
+    assert!(matches!(compact_indices(vec![0], u16::MAX as usize + 1), Indices::U32(_)));
+}
+
+#[test]
+fn face_mask_holds_any_set_of_faces() {
+    let mut mask = FaceMask::default();
+    assert_eq!(mask.exposed_faces().count(), 0);
+
+    mask.set_face(BlockFace::Back);
+    mask.set_face(BlockFace::Top);
+    mask.set_face(BlockFace::Top);
+
+    assert!(mask.has_face(BlockFace::Top));
+    assert!(mask.has_face(BlockFace::Back));
+    assert!(!mask.has_face(BlockFace::Left));
+    assert_eq!(
+        mask.exposed_faces().collect::<Vec<_>>(),
+        vec![BlockFace::Top, BlockFace::Back]
+    );
+}
+
+#[test]
+fn block_registry_follows_the_block_types() {
+    assert!(!BlockType::Air.is_solid() && BlockType::Air.is_transparent());
+    assert!(!BlockType::Water.is_solid() && BlockType::Water.is_transparent());
+    for block in [BlockType::Dirt, BlockType::Stone, BlockType::Grass] {
+        assert!(block.is_solid() && !block.is_transparent());
+    }
+
+    let registry = BlockRegistry::default();
+    for block in BlockType::ALL {
+        assert_eq!(registry.get(block).solid, block.is_solid());
+        assert_eq!(registry.get(block).transparent, block.is_transparent());
+    }
+}
+
+#[test]
+fn heightmap_world_has_an_unbreakable_bedrock_floor() {
+    let config = GenerationConfig {
+        terrain_mode: TerrainMode::Heightmap,
+        bedrock_layers: 3,
+        ..default()
+    };
+    let dims = config.chunk_dims();
+    let noise = TerrainNoise::new(&config);
+    let chunk_blocks = generate_chunk_blocks(IVec2XZ::new(-3, 1), &noise, &config);
+
+    for x in 0..dims.x {
+        for z in 0..dims.z {
+            for y in 0..3 {
+                assert!(chunk_blocks[block_index(x, y, z, dims)] == BlockType::Bedrock);
+            }
+            assert!(chunk_blocks[block_index(x, 3, z, dims)] != BlockType::Bedrock);
+        }
+    }
+
+    assert!(!BlockType::Bedrock.is_breakable());
+    assert!(BlockType::Stone.is_breakable());
+}
+
+#[test]
+fn reused_mesh_buffers_build_the_same_mesh() {
+    let config = GenerationConfig::default();
+    let noise = TerrainNoise::new(&config);
+    let registry = BlockRegistry::default();
+    let dims = config.chunk_dims();
+    let air = |_: IVec3| BlockType::Air;
+
+    let mut buffers = MeshBuffers::default();
+    let first = generate_chunk_blocks(IVec2XZ::new(0, 0), &noise, &config);
+    build_chunk_mesh_into(&mut buffers, &first, IVec2XZ::new(0, 0), dims, air, &registry);
+
+    // Nothing of the first chunk is left in the buffers.
+    let position = IVec2XZ::new(1, 0);
+    let second = generate_chunk_blocks(position, &noise, &config);
+    build_chunk_mesh_into(&mut buffers, &second, position, dims, air, &registry);
+    let fresh = build_chunk_mesh_data(&second, position, dims, air, &registry);
+
+    assert_eq!(buffers.vertices, fresh.vertices);
+    assert_eq!(buffers.indices, fresh.indices);
+    assert_eq!(buffers.uvs, fresh.uvs);
+    assert_eq!(buffers.colors, fresh.colors);
+}
+
+#[test]
+fn local_space_vertices_stay_small_far_from_the_origin() {
+    let dims = ChunkDims::new(CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE);
+    let chunk_blocks = vec![BlockType::Stone; dims.volume()];
+
+    let registry = BlockRegistry::default();
+    let air = |_: IVec3| BlockType::Air;
+
+    for position in [IVec2XZ::new(0, 0), IVec2XZ::new(50_000, -50_000)] {
+        let mut mesh = build_chunk_mesh(&chunk_blocks, position, dims, air, &registry);
+        translate_mesh(&mut mesh, -position.origin(dims).as_vec3());
+
+        let positions = positions(&mesh);
+        let max = Vec3::splat(CHUNK_SIZE as f32);
+        for &vertex in positions {
+            let vertex = Vec3::from_array(vertex);
+            assert!(vertex.cmpge(Vec3::ZERO).all() && vertex.cmple(max).all(), "{}", vertex);
+        }
+    }
+}
+
+#[test]
+fn face_normals_match_the_computed_ones() {
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(8, 100, 8, ChunkDims::default())] = BlockType::Stone;
+    let mesh = mesh_surrounded_by_air(&chunk_blocks);
+
+    let mut computed = mesh.clone();
+    compute_normals(&mut computed);
+    assert!(computed.indices().is_none());
+
+    let computed_normals = normals(&computed);
+    let normals = normals(&mesh);
+
+    // The duplicated vertices are in the order of the indices, and the winding decides the computed normals.
+    let indices: Vec<usize> = mesh.indices().unwrap().iter().collect();
+    assert_eq!(indices.len(), computed_normals.len());
+    for (computed_normal, index) in computed_normals.iter().zip(indices) {
+        let difference = Vec3::from_array(*computed_normal) - Vec3::from_array(normals[index]);
+        assert!(difference.length() < 1e-5, "{:?} vs {:?}", computed_normal, normals[index]);
+    }
+}
+
+#[test]
+fn block_meshes_split_the_faces_by_block_type() {
+    let dims = ChunkDims::default();
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(8, 100, 8, dims)] = BlockType::Stone;
+    chunk_blocks[block_index(9, 100, 8, dims)] = BlockType::Dirt;
+
+    let block_meshes = build_block_meshes(
+        &chunk_blocks,
+        IVec2XZ::new(0, 0),
+        dims,
+        |_| BlockType::Air,
+        &BlockRegistry::default(),
+        &mut MeshBuffers::default(),
+    );
+
+    // The face between the two blocks is hidden, the other 5 of each block are in the mesh of its type.
+    let types: Vec<u8> = block_meshes.iter().map(|(block, _)| *block as u8).collect();
+    assert_eq!(types, [BlockType::Stone as u8, BlockType::Dirt as u8]);
+    for (_, mesh) in &block_meshes {
+        assert_eq!(face_count(mesh), 5);
+    }
+}
+
+#[test]
+fn flat_world_is_solid_below_the_ground_height() {
+    let config = GenerationConfig {
+        terrain_mode: TerrainMode::Flat { ground_height: 8 },
+        ..default()
+    };
+    let dims = config.chunk_dims();
+    let chunk_blocks =
+        generate_chunk_blocks(IVec2XZ::new(7, -7), &TerrainNoise::new(&config), &config);
+
+    for (index, &block) in chunk_blocks.iter().enumerate() {
+        let (_, y, _) = block_position(index, dims);
+        assert_eq!(block.is_solid(), y < 8, "y {}", y);
+    }
+}
+
+#[test]
+fn dirt_and_stone_faces_use_different_atlas_tiles() {
+    let dims = ChunkDims::default();
+    let uvs_of = |block: BlockType| {
+        let mut chunk_blocks = empty_chunk();
+        chunk_blocks[block_index(8, 100, 8, dims)] = block;
+        mesh_data_surrounded_by_air(&chunk_blocks).uvs
+    };
+
+    let (dirt, stone) = (uvs_of(BlockType::Dirt), uvs_of(BlockType::Stone));
+    assert_eq!(dirt.len(), stone.len());
+    assert_ne!(dirt, stone);
+
+    // Every face of a block shows a single tile of the atlas.
+    let tile_size = 1. / ATLAS_TILES as f32;
+    for uvs in [dirt, stone] {
+        let (min, max) = uvs.iter().fold((Vec2::ONE, Vec2::ZERO), |(min, max), &uv| {
+            (min.min(Vec2::from(uv)), max.max(Vec2::from(uv)))
+        });
+        assert!((max - min).cmple(Vec2::splat(tile_size)).all());
+    }
+}
+
+#[test]
+fn chunk_edge_ao_matches_a_single_bigger_chunk() {
+    let registry = BlockRegistry::default();
+    let small_dims = ChunkDims::default();
+    let big_dims = ChunkDims::new(2 * CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE);
+
+    // A floor across the border between X 15 and 16, with a wall block standing on it in the chunk to the east.
+    let mut big_blocks = vec![BlockType::Air; big_dims.volume()];
+    for x in 12..20 {
+        for z in 4..12 {
+            big_blocks[block_index(x, 10, z, big_dims)] = BlockType::Stone;
+        }
+    }
+    big_blocks[block_index(16, 11, 8, big_dims)] = BlockType::Stone;
+
+    let big_block = |pos: IVec3| {
+        let inside =
+            (0..big_dims.x as i32).contains(&pos.x) && (0..big_dims.z as i32).contains(&pos.z);
+        world_block_index(pos, big_dims)
+            .filter(|_| inside)
+            .map_or(BlockType::Air, |index| big_blocks[index])
+    };
+    let mut small_blocks = empty_chunk();
+    for (index, block) in small_blocks.iter_mut().enumerate() {
+        let (x, y, z) = block_position(index, small_dims);
+        *block = big_block(IVec3::new(x as i32, y as i32, z as i32));
+    }
+
+    let origin = IVec2XZ::new(0, 0);
+    let big = build_chunk_mesh_data(&big_blocks, origin, big_dims, |_| BlockType::Air, &registry);
+    let small = build_chunk_mesh_data(&small_blocks, origin, small_dims, big_block, &registry);
+
+    // Every corner of the small chunk is as dark as the same corner in the bigger chunk.
+    let mut darkened_edge_corners = 0;
+    for i in 0..small.vertices.len() {
+        let (position, normal) = (small.vertices[i], small.normals[i]);
+        let same_corner = (0..big.vertices.len())
+            .find(|&j| big.vertices[j] == position && big.normals[j] == normal)
+            .expect("corner missing from the bigger chunk");
+        assert_eq!(small.colors[i], big.colors[same_corner], "corner at {:?}", position);
+
+        if position[0] == CHUNK_SIZE as f32 && small.colors[i][0] < 1. {
+            darkened_edge_corners += 1;
+        }
+    }
+    // The wall darkens the floor corners right at the border.
+    assert!(darkened_edge_corners > 0);
+}
+
+#[test]
+fn every_meshing_mode_closes_a_cube() {
+    let registry = BlockRegistry::default();
+    let dims = ChunkDims::default();
+    let position = IVec2XZ::new(0, 0);
+    let air = |_: IVec3| BlockType::Air;
+
+    // An 8 block cube, lined up with the groups of every level of detail.
+    let mut chunk_blocks = empty_chunk();
+    for x in 4..12 {
+        for y in 96..104 {
+            for z in 4..12 {
+                chunk_blocks[block_index(x, y, z, dims)] = BlockType::Stone;
+            }
+        }
+    }
+
+    for mode in MeshingMode::CYCLE {
+        let lod = mode.lod(1);
+        let mesh = if lod > 1 {
+            build_lod_mesh(&chunk_blocks, position, dims, lod, mode, &registry, 0).0
+        } else if mode == MeshingMode::Greedy {
+            greedy_mesh(&chunk_blocks, position, dims, air, &registry)
+        } else {
+            build_chunk_mesh(&chunk_blocks, position, dims, air, &registry)
+        };
+
+        // All six sides are there and covered completely, so there are no holes.
+        let areas = area_by_normal(&mesh);
+        assert_eq!(areas.len(), 6, "{:?}", mode);
+        for (normal, area) in areas {
+            assert!((area - 64.).abs() < 0.01, "{:?} {:?}: {}", mode, normal, area);
+        }
+    }
+}
+
+#[test]
+fn fallback_atlas_gives_every_block_its_own_color() {
+    let registry = BlockRegistry::default();
+    let atlas = fallback_atlas(ATLAS_TILES, 2);
+    let size = (ATLAS_TILES * 2) as usize;
+    assert_eq!(atlas.data.len(), size * size * 4);
+
+    let tile_color = |tile: u32| {
+        let (x, y) = ((tile % ATLAS_TILES * 2) as usize, (tile / ATLAS_TILES * 2) as usize);
+        // Every pixel of the tile has the same color.
+        let pixels = [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)]
+            .map(|(x, y)| &atlas.data[(x + y * size) * 4..][..4]);
+        assert!(pixels.iter().all(|&pixel| pixel == pixels[0]));
+        pixels[0].to_vec()
+    };
+
+    let mut colors: Vec<Vec<u8>> = BlockType::ALL
+        .into_iter()
+        .filter(|&block| block != BlockType::Air)
+        .map(|block| tile_color(registry.get(block).textures.side))
+        .collect();
+    colors.sort();
+    colors.dedup();
+    assert_eq!(colors.len(), BlockType::ALL.len() - 1);
+}
+
+#[test]
+fn sphere_is_air_beyond_its_roughest_surface() {
+    let center = IVec3::new(8, 128, 8);
+    let radius = 20.;
+    let config = GenerationConfig {
+        terrain_mode: TerrainMode::Sphere { center, radius },
+        ..default()
+    };
+    let noise = TerrainNoise::new(&config);
+    let dims = config.chunk_dims();
+
+    let mut solid = 0;
+    for chunk_position in [IVec2XZ::new(0, 0), IVec2XZ::new(-1, 0), IVec2XZ::new(1, 1)] {
+        let chunk_blocks = generate_chunk_blocks(chunk_position, &noise, &config);
+        for (index, &block) in chunk_blocks.iter().enumerate() {
+            let (x, y, z) = block_position(index, dims);
+            let local = IVec3::new(x as i32, y as i32, z as i32);
+            let distance = (chunk_local_to_world(chunk_position, local, dims) - center)
+                .as_vec3()
+                .length();
+
+            if distance > radius + SPHERE_ROUGHNESS {
+                assert!(block == BlockType::Air, "solid block {} away", distance);
+            }
+            if distance < radius - SPHERE_ROUGHNESS {
+                assert!(block.is_solid(), "air {} away", distance);
+            }
+            solid += block.is_solid() as usize;
+        }
+    }
+    assert!(solid > 0);
+}
+
+#[test]
+fn shared_noise_is_only_rebuilt_for_a_new_seed() {
+    let mut config = GenerationConfig::default();
+    let mut shared = SharedTerrainNoise::new(&config);
+    let noise = shared.get(&config);
+
+    // Settings that only change how the noise is sampled keep it.
+    config.cave_threshold += 0.1;
+    shared.update(&config);
+    assert!(Arc::ptr_eq(&noise, &shared.get(&config)));
+
+    // A new seed gets a new noise right away, even before the update.
+    config.seed += 1;
+    let fresh = TerrainNoise::new(&config);
+    let pos = [12.3, 45.6, 78.9];
+    assert_eq!(shared.get(&config).cave.get(pos), fresh.cave.get(pos));
+    assert_ne!(noise.cave.get(pos), fresh.cave.get(pos));
+
+    shared.update(&config);
+    assert!(!Arc::ptr_eq(&noise, &shared.get(&config)));
+}
+
+#[test]
+fn dedup_merges_only_identical_vertices() {
+    // Two triangles of a quad that repeat the corners of their shared edge, and a third one shaded differently.
+    let corners = [[0., 0., 0.], [1., 0., 0.], [1., 0., 1.], [0., 0., 1.]];
+    let mut data = ChunkMeshData::default();
+    for corner in [0, 1, 2, 0, 2, 3, 0, 1, 2] {
+        data.vertices.push(corners[corner]);
+        data.normals.push([0., 1., 0.]);
+        data.uvs.push([corners[corner][0], corners[corner][2]]);
+        data.colors.push([1.; 4]);
+    }
+    for color in &mut data.colors[6..] {
+        *color = [0.5, 0.5, 0.5, 1.];
+    }
+    data.indices = (0..9).collect();
+
+    let deduped = data.dedup_vertices();
+
+    assert_eq!(deduped.vertices.len(), 4 + 3);
+    assert_eq!(deduped.indices, [0, 1, 2, 0, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn only_glowstone_tiles_are_emissive() {
+    let registry = BlockRegistry::default();
+    let atlas = emissive_atlas(&registry, ATLAS_TILES, 1);
+
+    let tile_color = |block: BlockType| {
+        let tile = registry.get(block).textures.side as usize;
+        atlas.data[tile * 4..][..4].to_vec()
+    };
+
+    assert_eq!(tile_color(BlockType::Glowstone), BlockType::Glowstone.emissive().as_rgba_u8());
+    for block in BlockType::ALL {
+        if block != BlockType::Glowstone && block != BlockType::Air {
+            assert_eq!(tile_color(block)[..3], [0, 0, 0]);
+        }
+    }
+}
+
+#[test]
+fn block_light_fades_with_distance_from_glowstone() {
+    let dims = ChunkDims::default();
+    let registry = BlockRegistry::default();
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(8, 100, 8, dims)] = BlockType::Glowstone;
+    // A wall the light has to go around.
+    for y in 95..106 {
+        for z in 3..14 {
+            chunk_blocks[block_index(10, y, z, dims)] = BlockType::Stone;
+        }
+    }
+
+    let light = propagate_block_light(&chunk_blocks, dims, &registry);
+
+    let level = |x: i32, y: i32, z: i32| light.level(IVec3::new(x, y, z));
+    assert_eq!(level(8, 100, 8), MAX_LIGHT);
+    for distance in 1..MAX_LIGHT as i32 {
+        assert_eq!(level(8, 100 + distance, 8), MAX_LIGHT - distance as u8);
+        assert_eq!(level(8 - distance.min(8), 100, 8), MAX_LIGHT - distance.min(8) as u8);
+    }
+    // The light runs out eventually.
+    assert_eq!(level(8, 100 + MAX_LIGHT as i32 + 3, 8), 0);
+    // Walls block the light, and behind them it only arrives the long way around.
+    assert_eq!(level(10, 100, 8), 0);
+    assert!(level(11, 100, 8) < level(9, 100, 8) - 2);
+}
+
+#[test]
+fn skylight_stops_at_the_first_opaque_block() {
+    let dims = ChunkDims::default();
+    let registry = BlockRegistry::default();
+    let mut chunk_blocks = empty_chunk();
+    for x in 0..dims.x {
+        for z in 0..dims.z {
+            for y in 0..60 {
+                chunk_blocks[block_index(x, y, z, dims)] = BlockType::Stone;
+            }
+            // A pond on the left half.
+            if x < 8 {
+                for y in 60..64 {
+                    chunk_blocks[block_index(x, y, z, dims)] = BlockType::Water;
+                }
+            }
+        }
+    }
+    // An overhang with a cave below it.
+    chunk_blocks[block_index(12, 70, 12, dims)] = BlockType::Stone;
+    chunk_blocks[block_index(12, 59, 12, dims)] = BlockType::Air;
+
+    let light = compute_skylight(&chunk_blocks, dims, &registry);
+    let level = |x: i32, y: i32, z: i32| light.level(IVec3::new(x, y, z));
+
+    assert_eq!(level(12, 100, 4), MAX_LIGHT);
+    assert_eq!(level(12, 60, 4), MAX_LIGHT);
+    assert_eq!(level(12, 55, 4), 0);
+    // Below the overhang and in the cave under it.
+    assert_eq!(level(12, 69, 12), 0);
+    assert_eq!(level(12, 59, 12), 0);
+    // The water gets darker with depth.
+    assert_eq!(level(4, 63, 4), MAX_LIGHT - 1);
+    assert_eq!(level(4, 60, 4), MAX_LIGHT - 4);
+
+    // Combined with the block light, the brighter one wins.
+    chunk_blocks[block_index(12, 61, 12, dims)] = BlockType::Glowstone;
+    let light = compute_skylight(&chunk_blocks, dims, &registry)
+        .max(propagate_block_light(&chunk_blocks, dims, &registry));
+    assert_eq!(light.level(IVec3::new(12, 62, 12)), MAX_LIGHT - 1);
+    assert_eq!(light.level(IVec3::new(12, 100, 4)), MAX_LIGHT);
+}
+
+#[test]
+fn simplified_lod_mesh_drops_only_the_small_quads() {
+    let registry = BlockRegistry::default();
+    let dims = ChunkDims::default();
+    let position = IVec2XZ::new(0, 0);
+
+    // A floor with a single coarse block standing on it.
+    let mut chunk_blocks = empty_chunk();
+    for x in 0..16 {
+        for z in 0..16 {
+            for y in 10..12 {
+                chunk_blocks[block_index(x, y, z, dims)] = BlockType::Stone;
+            }
+        }
+    }
+    for x in 8..10 {
+        for z in 8..10 {
+            for y in 12..14 {
+                chunk_blocks[block_index(x, y, z, dims)] = BlockType::Stone;
+            }
+        }
+    }
+
+    // The threshold is rounded up to coarse blocks, so the farther level of detail is simplified at least as much.
+    for (lod, min_face_voxels) in [(2, 8), (4, 24)] {
+        let mesh = |min_face_voxels| {
+            let greedy = MeshingMode::Greedy;
+            build_lod_mesh(&chunk_blocks, position, dims, lod, greedy, &registry, min_face_voxels).0
+        };
+        let (full, simplified) = (mesh(0), mesh(min_face_voxels));
+        assert!(face_count(&simplified) < face_count(&full), "lod {}", lod);
+
+        // Every quad that's left covers at least `min_face_voxels` blocks.
+        let positions = positions(&simplified);
+        let indices: Vec<usize> = simplified.indices().unwrap().iter().collect();
+        for quad in indices.chunks(6) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from_array(positions[quad[i]]));
+            let area = (b - a).cross(c - a).length();
+            assert!(area >= min_face_voxels as f32, "quad at {} with lod {}", a, lod);
+        }
+
+        // The big faces of the floor are untouched.
+        let bottom = |mesh: &Mesh| {
+            area_by_normal(mesh)
+                .into_iter()
+                .find(|(normal, _)| *normal == [0, -1, 0])
+                .map(|(_, area)| area)
+        };
+        assert_eq!(bottom(&simplified), Some(256.));
+        assert_eq!(bottom(&full), Some(256.));
+    }
+}
+
+#[test]
+fn noise_image_maps_the_noise_to_gray_and_tints_the_caves() {
+    assert_eq!(noise_to_gray(-1.0), 0);
+    assert_eq!(noise_to_gray(1.0), 255);
+    assert_eq!(noise_to_gray(3.0), 255);
+
+    let config = GenerationConfig::default();
+    let noise = TerrainNoise::new(&config);
+    let image = noise_image(&noise.cave, &config, 16);
+    assert_eq!(image.data.len(), 16 * 16 * 4);
+
+    // The top left pixel is the block at the corner of the slice.
+    let corner = config.spawn_center - IVec3::new(8, 0, 8);
+    let value = noise.cave.get([
+        corner.x as f64 * config.cave_scale,
+        config.sea_level as f64 * config.cave_scale,
+        corner.z as f64 * config.cave_scale,
+    ]);
+    let gray = noise_to_gray(value);
+    let expected = if value < config.cave_threshold {
+        [gray, gray, gray, 255]
+    } else {
+        [gray, gray / 2, gray / 2, 255]
+    };
+    assert_eq!(image.data[..4], expected);
+}
+
+#[test]
+fn synchronous_generation_loads_every_chunk_in_one_frame() {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .add_asset::<Mesh>()
+        .insert_resource(GenerationConfig {
+            synchronous: true,
+            ..default()
+        })
+        .insert_resource(Generating(true))
+        .insert_resource(RenderDistance(2))
+        .insert_resource(ChunkMaterials {
+            terrain: Handle::default(),
+            water: Handle::default(),
+        })
+        .insert_resource(ChunkTextures {
+            array: Handle::default(),
+            material: Handle::default(),
+        })
+        .init_resource::<ChunksLoaded>()
+        .init_resource::<ChunkProgress>()
+        .init_resource::<BlockRegistry>()
+        .init_resource::<BlockMaterials>()
+        .init_resource::<ModifiedChunks>()
+        .init_resource::<SharedTerrainNoise>()
+        .init_resource::<MeshBufferPool>()
+        .add_systems(Update, (chunk_system, apply_deferred, handle_mesh_tasks).chain());
+    app.world.spawn((Camera3d::default(), Transform::default()));
+
+    app.update();
+
+    let chunks_loaded = app.world.resource::<ChunksLoaded>();
+    assert_eq!(
+        chunks_loaded.chunks.len() as u32,
+        app.world.resource::<ChunkProgress>().total
+    );
+    assert!(!chunks_loaded.chunks.is_empty());
+    for (position, &entity) in &chunks_loaded.chunks {
+        let chunk = app.world.entity(entity);
+        assert!(!chunk.contains::<ComputeMeshTask>(), "{:?} is still generating", position);
+        assert!(chunk.contains::<ChunkData>(), "{:?} has no blocks", position);
+        assert!(chunk.contains::<Handle<Mesh>>(), "{:?} has no mesh", position);
+    }
+}
+
+#[test]
+fn all_faces_point_in_six_different_directions() {
+    let faces: std::collections::HashSet<BlockFace> = BlockFace::ALL.into_iter().collect();
+    assert_eq!(faces.len(), 6);
+
+    let offsets: std::collections::HashSet<IVec3> =
+        BlockFace::ALL.into_iter().map(BlockFace::offset).collect();
+    assert_eq!(offsets.len(), 6);
+    assert_eq!(BlockFace::ALL.into_iter().map(BlockFace::offset).sum::<IVec3>(), IVec3::ZERO);
+}
+
+#[test]
+fn validate_mesh_catches_mismatched_attributes_and_indices() {
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(4, 4, 4, ChunkDims::default())] = BlockType::Stone;
+    let mesh = mesh_surrounded_by_air(&chunk_blocks);
+    assert_eq!(validate_mesh(&mesh), Ok(()));
+
+    let mut out_of_range = mesh.clone();
+    out_of_range.set_indices(Some(Indices::U32(vec![0, 1, 24])));
+    assert!(validate_mesh(&out_of_range).is_err());
+
+    let mut missing_normal = mesh;
+    missing_normal.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 1.0, 0.0]; 23]);
+    assert!(validate_mesh(&missing_normal).is_err());
+}
+
+#[test]
+fn surface_normal_points_along_the_up_axis() {
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(8, 100, 8, ChunkDims::default())] = BlockType::Grass;
+
+    for (up_axis, up) in [(UpAxis::Y, Vec3::Y), (UpAxis::Z, Vec3::Z)] {
+        let mut mesh = mesh_surrounded_by_air(&chunk_blocks);
+        convert_up_axis(&mut mesh, up_axis);
+
+        let positions = positions(&mesh);
+        let normals = normals(&mesh);
+
+        // The surface is the face with the highest vertices along the up axis.
+        let height = |position: &[f32; 3]| Vec3::from_array(*position).dot(up);
+        let top = positions.iter().map(height).fold(f32::MIN, f32::max);
+        let surface_normals = positions
+            .iter()
+            .zip(normals)
+            .filter(|(position, _)| height(position) == top)
+            .filter(|(_, normal)| Vec3::from_array(**normal) == up)
+            .count();
+        assert_eq!(surface_normals, 4);
+    }
+}
+
+/// The vertex data and triangles of every quad, relative to the quad and sorted, so meshes with the same faces in a
+/// different order compare equal.
+fn sorted_quads(data: &ChunkMeshData) -> Vec<Vec<u32>> {
+    let mut quads: Vec<Vec<u32>> = (0..data.vertices.len() / 4)
+        .map(|quad| {
+            let vertices = quad * 4..quad * 4 + 4;
+            let floats = data.vertices[vertices.clone()]
+                .iter()
+                .flatten()
+                .chain(data.normals[vertices.clone()].iter().flatten())
+                .chain(data.uvs[vertices.clone()].iter().flatten())
+                .chain(data.colors[vertices].iter().flatten())
+                .map(|value| value.to_bits());
+            let triangles = data.indices[quad * 6..quad * 6 + 6]
+                .iter()
+                .map(|index| index - quad as u32 * 4);
+            floats.chain(triangles).collect()
+        })
+        .collect();
+    quads.sort();
+    quads
+}
+
+#[test]
+fn patched_mesh_matches_a_rebuilt_one() {
+    let dims = ChunkDims::default();
+    let registry = BlockRegistry::default();
+    let mut chunk_blocks = empty_chunk();
+    for x in 4..9 {
+        for y in 4..9 {
+            for z in 4..9 {
+                chunk_blocks[block_index(x, y, z, dims)] = BlockType::Stone;
+            }
+        }
+    }
+
+    let build = |chunk_blocks: &[BlockType]| {
+        let mut buffers = MeshBuffers::default();
+        build_chunk_mesh_into(
+            &mut buffers,
+            chunk_blocks,
+            IVec2XZ::new(2, 1),
+            dims,
+            |_| BlockType::Air,
+            &registry,
+        );
+        let faces = buffers.faces.clone();
+        (buffers.into_data(), faces)
+    };
+    let (mut data, mut faces) = build(&chunk_blocks);
+
+    // Dig into a corner and put a block on top, which changes the faces and the ambient occlusion around both.
+    let edited = [IVec3::new(8, 8, 8), IVec3::new(6, 9, 6)];
+    chunk_blocks[block_index(8, 8, 8, dims)] = BlockType::Air;
+    chunk_blocks[block_index(6, 9, 6, dims)] = BlockType::Dirt;
+    assert!(patch_chunk_mesh(
+        &mut data,
+        &mut faces,
+        &edited,
+        &chunk_blocks,
+        IVec2XZ::new(2, 1),
+        dims,
+        |_| BlockType::Air,
+        &registry,
+        Vec3::ZERO,
+    ));
+
+    let (rebuilt, mut rebuilt_faces) = build(&chunk_blocks);
+    assert_eq!(sorted_quads(&data), sorted_quads(&rebuilt));
+    let face_key = |&(pos, face): &(IVec3, BlockFace)| (pos.to_array(), face as u8);
+    faces.sort_by_key(face_key);
+    rebuilt_faces.sort_by_key(face_key);
+    assert_eq!(faces, rebuilt_faces);
+
+    // Bookkeeping that doesn't match the mesh is refused.
+    faces.pop();
+    assert!(!patch_chunk_mesh(
+        &mut data,
+        &mut faces,
+        &edited,
+        &chunk_blocks,
+        IVec2XZ::new(2, 1),
+        dims,
+        |_| BlockType::Air,
+        &registry,
+        Vec3::ZERO,
+    ));
+}
+
+#[test]
+fn suggested_cave_threshold_hits_the_target_solid_fraction() {
+    let config = GenerationConfig::default();
+    let samples = 1000;
+
+    let all_caves = GenerationConfig {
+        cave_threshold: -1.,
+        ..config
+    };
+    let no_caves = GenerationConfig {
+        cave_threshold: 1.,
+        ..config
+    };
+    assert!(
+        estimate_solid_fraction(&all_caves, samples) < estimate_solid_fraction(&no_caves, samples)
+    );
+
+    let threshold = suggest_cave_threshold(&config, 0.4, samples);
+    let suggested = GenerationConfig {
+        cave_threshold: threshold,
+        ..config
+    };
+    assert!((estimate_solid_fraction(&suggested, samples) - 0.4).abs() < 0.05);
+}
+
+#[test]
+fn smooth_normals_point_out_of_the_corners() {
+    let mut chunk_blocks = empty_chunk();
+    chunk_blocks[block_index(4, 4, 4, ChunkDims::default())] = BlockType::Stone;
+    let mut mesh = mesh_surrounded_by_air(&chunk_blocks);
+    smooth_normals(&mut mesh);
+
+    let positions = positions(&mesh);
+    let normals = normals(&mesh);
+
+    // Every corner of a lone block is shared by three faces, so its normal points away from the block diagonally.
+    let center = Vec3::splat(4.5);
+    for (position, normal) in positions.iter().zip(normals) {
+        let expected = (Vec3::from_array(*position) - center).normalize();
+        assert!(Vec3::from_array(*normal).abs_diff_eq(expected, 1e-5));
+    }
+}
+
+#[test]
+fn try_block_is_none_outside_of_the_chunk() {
+    let dims = ChunkDims { x: 2, y: 3, z: 4 };
+    let mut chunk_blocks = vec![BlockType::Air; dims.volume()];
+    chunk_blocks[block_index(1, 2, 3, dims)] = BlockType::Stone;
+
+    assert!(try_block(&chunk_blocks, IVec3::new(1, 2, 3), dims) == Some(BlockType::Stone));
+    assert!(try_block(&chunk_blocks, IVec3::ZERO, dims) == Some(BlockType::Air));
+    for outside in [
+        IVec3::new(-1, 0, 0),
+        IVec3::new(0, -1, 0),
+        IVec3::new(0, 0, -1),
+        IVec3::new(2, 0, 0),
+        IVec3::new(0, 3, 0),
+        IVec3::new(0, 0, 4),
+    ] {
+        assert!(try_block(&chunk_blocks, outside, dims).is_none());
+    }
+
+    // An empty chunk has nothing in it, and nothing underflows.
+    let empty = ChunkDims { x: 0, y: 0, z: 0 };
+    assert!(try_block(&[], IVec3::ZERO, empty).is_none());
+}
+
+#[test]
+fn carving_out_a_buried_block_adds_its_inner_faces() {
+    let config = GenerationConfig::default();
+    let dims = config.chunk_dims();
+    let chunk_position = IVec2XZ::new(0, 0);
+    let (chunk_blocks, mesh) = regenerate_chunk(chunk_position, &config, &[]);
+
+    // A block with stone all around it, so none of its neighbours shows a face yet.
+    let buried = (1..dims.y as i32 - 1)
+        .flat_map(|y| (1..dims.z as i32 - 1).map(move |z| (y, z)))
+        .flat_map(|(y, z)| (1..dims.x as i32 - 1).map(move |x| IVec3::new(x, y, z)))
+        .find(|&pos| {
+            std::iter::once(IVec3::ZERO)
+                .chain(BlockFace::ALL.map(|face| face.offset()))
+                .all(|offset| {
+                    try_block(&chunk_blocks, pos + offset, dims) == Some(BlockType::Stone)
+                })
+        })
+        .expect("the default terrain has stone below the surface");
+    let index = local_block_index(buried, dims).unwrap();
+
+    let (carved_blocks, carved_mesh) =
+        regenerate_chunk(chunk_position, &config, &[(index, BlockType::Air)]);
+    assert!(carved_blocks[index] == BlockType::Air);
+    // Each of the six neighbours now shows the face towards the hole.
+    assert_eq!(face_count(&carved_mesh), face_count(&mesh) + 6);
+}
+
+#[test]
+fn ridged_terrain_is_the_same_for_the_same_seed() {
+    let config = GenerationConfig {
+        terrain_mode: TerrainMode::Ridged3D {
+            frequency: 0.03,
+            octaves: 4,
+        },
+        ..default()
+    };
+    let generate = |config: &GenerationConfig| {
+        generate_chunk_blocks(IVec2XZ::new(2, -1), &TerrainNoise::new(config), config)
+    };
+
+    let first = generate(&config);
+    assert!(first == generate(&config));
+    assert!(first.iter().any(|block| block.is_solid()));
+
+    let other_seed = GenerationConfig {
+        seed: config.seed + 1,
+        ..config
+    };
+    assert!(first != generate(&other_seed));
+}
+
+#[test]
+fn camera_spawns_above_the_ground() {
+    let config = GenerationConfig {
+        terrain_mode: TerrainMode::Flat { ground_height: 8 },
+        spawn_center: IVec3::new(5, 2, -7),
+        ..default()
+    };
+    let spawn = spawn_position(&TerrainNoise::new(&config), &config);
+    assert_eq!(spawn, IVec3::new(5, 8 + SPAWN_CLEARANCE, -7));
+
+    // Without any ground the spawn center is kept as it is.
+    let empty = GenerationConfig {
+        terrain_mode: TerrainMode::Flat { ground_height: 0 },
+        ..config
+    };
+    assert_eq!(spawn_position(&TerrainNoise::new(&empty), &empty), empty.spawn_center);
+}
+
+#[test]
+fn resized_edits_stay_at_the_same_world_position() {
+    let old_dims = ChunkDims { x: 16, y: 64, z: 16 };
+    let new_dims = ChunkDims { x: 8, y: 32, z: 24 };
+    let kept = IVec3::new(-5, 20, 17);
+    let too_high = IVec3::new(3, 40, 3);
+
+    let mut modified = ModifiedChunks {
+        dims: old_dims,
+        ..default()
+    };
+    for pos in [kept, too_high] {
+        let index = world_block_index(pos, old_dims).unwrap();
+        modified.record(IVec2XZ::from_block(pos, old_dims), index, BlockType::Log);
+    }
+
+    modified.resize(new_dims);
+    assert!(modified.block(kept, new_dims) == Some(BlockType::Log));
+    assert_eq!(modified.chunks.values().flatten().count(), 1);
+
+    // Resizing to the same size again changes nothing.
+    modified.resize(new_dims);
+    assert!(modified.block(kept, new_dims) == Some(BlockType::Log));
+}